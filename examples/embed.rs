@@ -0,0 +1,15 @@
+//! Drives the explorer from an application-owned event loop, using only the
+//! prelude. Building this example keeps the public API honest.
+
+use wgpu_fractals::prelude::*;
+use winit::event_loop::EventLoop;
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let event_loop = EventLoop::<UserEvent>::with_user_event().build()?;
+    let mut app = App::new();
+    event_loop.run_app(&mut app)?;
+
+    Ok(())
+}
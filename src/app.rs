@@ -1,26 +1,66 @@
+use crate::command::Command;
 use crate::state;
 use std::sync::Arc;
+use std::time::Duration;
 
+use web_time::Instant;
 use winit::{
     application::ApplicationHandler,
-    event::{KeyEvent, MouseButton},
-    event_loop::ActiveEventLoop,
+    event::{KeyEvent, MouseButton, MouseScrollDelta},
+    event_loop::{ActiveEventLoop, ControlFlow},
     keyboard::PhysicalKey,
     window::{self, WindowAttributes},
 };
 
+// Fraction of the window one notch of a tilt wheel pans by
+const LINE_SCROLL_PAN: f64 = 0.05;
+// How long to wait before trying again when the GPU state can't be created
+const STATE_RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// User event type of the [`App`] event loop. Only the app itself sends
+/// these, so the contents are private.
+pub struct UserEvent(AppEvent);
+
+// Events delivered to the app through the event loop proxy. Only the
+// web sends any; natively this is empty.
+enum AppEvent {
+    /// The asynchronously created State is ready.
+    #[cfg(target_arch = "wasm32")]
+    StateReady(Box<state::State>),
+    /// Creating the State for this window failed; the error is logged.
+    #[cfg(target_arch = "wasm32")]
+    StateFailed(Arc<window::Window>),
+    /// At least a sliver of the canvas is (or is no longer) on screen.
+    #[cfg(target_arch = "wasm32")]
+    CanvasVisible(bool),
+    /// The page was shown or hidden, e.g. by switching browser tabs.
+    #[cfg(target_arch = "wasm32")]
+    PageVisible(bool),
+}
+
+/// Winit application handler owning the window and all GPU state.
 pub struct App {
     #[cfg(target_arch = "wasm32")]
-    proxy: Option<winit::event_loop::EventLoopProxy<State>>,
+    proxy: Option<winit::event_loop::EventLoopProxy<UserEvent>>,
     state: Option<state::State>,
+    // What survives of a State whose device was lost, until its
+    // replacement is ready
+    retained: Option<state::RetainedState>,
+    // A window whose State couldn't be created, and when to try again
+    retry: Option<(Arc<window::Window>, Instant)>,
+    #[cfg(target_arch = "wasm32")]
+    canvas_visible: bool,
+    #[cfg(target_arch = "wasm32")]
+    page_visible: bool,
 }
 
-impl ApplicationHandler<state::State> for App {
+impl ApplicationHandler<UserEvent> for App {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        let window_attributes = WindowAttributes::default();
+        #[allow(unused_mut)]
+        let mut window_attributes = WindowAttributes::default();
         #[cfg(target_arch = "wasm32")]
-        {
-            use wasm_bindgen::JsCast;
+        let canvas = {
+            use wasm_bindgen::{JsCast, UnwrapThrowExt};
             use winit::platform::web::WindowAttributesExtWebSys;
 
             const CANVAS_ID: &str = "canvas";
@@ -28,47 +68,60 @@ impl ApplicationHandler<state::State> for App {
             let window = wgpu::web_sys::window().unwrap_throw();
             let document = window.document().unwrap_throw();
             let canvas = document.get_element_by_id(CANVAS_ID).unwrap_throw();
-            let html_canvas_element = canvas.unchecked_into();
+            let html_canvas_element = canvas.clone().unchecked_into();
             window_attributes = window_attributes.with_canvas(Some(html_canvas_element));
-        }
+            canvas
+        };
         let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
 
-        #[cfg(not(target_arch = "wasm32"))]
-        {
-            // If we are not on web we can use pollster to
-            // await the
-            self.state = Some(pollster::block_on(state::State::new(window)).unwrap());
+        #[cfg(target_arch = "wasm32")]
+        if let Some(proxy) = &self.proxy {
+            watch_visibility(proxy, &canvas);
         }
+        self.create_state(window);
+    }
 
-        #[cfg(target_arch = "wasm32")]
-        {
-            if let Some(proxy) = self.proxy.take() {
-                wasm_bindgen_futures::spawn_local(async move {
-                    assert!(
-                        proxy
-                            .send_event(
-                                State::new(window)
-                                    .await
-                                    .expect("Unable to create canvas!!!")
-                            )
-                            .is_ok()
-                    )
-                });
+    fn user_event(&mut self, _event_loop: &ActiveEventLoop, event: UserEvent) {
+        // This is where proxy.send_event() ends up
+        match event.0 {
+            #[cfg(target_arch = "wasm32")]
+            AppEvent::StateReady(mut state) => {
+                let size = state.window.inner_size();
+                state.resize(size.width, size.height);
+                if let Some(retained) = self.retained.take() {
+                    state.restore(retained);
+                }
+                state.set_visible(self.visible());
+                state.request_redraw();
+                self.state = Some(*state);
+            }
+            #[cfg(target_arch = "wasm32")]
+            AppEvent::StateFailed(window) => self.state_failed(window),
+            #[cfg(target_arch = "wasm32")]
+            AppEvent::CanvasVisible(visible) => {
+                self.canvas_visible = visible;
+                self.update_visibility();
+            }
+            #[cfg(target_arch = "wasm32")]
+            AppEvent::PageVisible(visible) => {
+                self.page_visible = visible;
+                self.update_visibility();
             }
         }
     }
 
-    fn user_event(&mut self, _event_loop: &ActiveEventLoop, event: state::State) {
-        // This is where proxy.send_event() ends up
-        #[cfg(target_arch = "wasm32")]
-        {
-            event.window.request_redraw();
-            event.resize(
-                event.window.inner_size().width,
-                event.window.inner_size().height,
-            );
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        let Some((_, retry_at)) = self.retry else {
+            return;
+        };
+        if Instant::now() < retry_at {
+            event_loop.set_control_flow(ControlFlow::WaitUntil(retry_at));
+            return;
+        }
+        event_loop.set_control_flow(ControlFlow::Wait);
+        if let Some((window, _)) = self.retry.take() {
+            self.create_state(window);
         }
-        self.state = Some(event);
     }
 
     fn window_event(
@@ -86,12 +139,27 @@ impl ApplicationHandler<state::State> for App {
         use winit::event::WindowEvent;
         match event {
             WindowEvent::CloseRequested => event_loop.exit(),
+            // The web build tracks visibility itself through the proxy
+            #[cfg(not(target_arch = "wasm32"))]
+            WindowEvent::Occluded(occluded) => app_state.set_visible(!occluded),
             WindowEvent::Resized(size) => app_state.resize(size.width, size.height),
+            // A dropped PNG from a screenshot or export brings back its view
+            #[cfg(not(target_arch = "wasm32"))]
+            WindowEvent::DroppedFile(path) => app_state.load_settings(&path),
+            WindowEvent::Moved(_) => {
+                // A new monitor may prefer a different surface format
+                app_state.refresh_surface_format();
+            }
             WindowEvent::RedrawRequested => {
+                if app_state.is_device_lost() {
+                    self.recover_device();
+                    return;
+                }
                 app_state.update();
                 match app_state.render() {
                     Ok(_) => {}
                     Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                        app_state.refresh_surface_format();
                         let size = app_state.window.inner_size();
                         app_state.resize(size.width, size.height);
                     }
@@ -100,6 +168,7 @@ impl ApplicationHandler<state::State> for App {
                     }
                 }
             }
+            WindowEvent::ModifiersChanged(modifiers) => app_state.modifiers = modifiers.state(),
             WindowEvent::KeyboardInput {
                 event:
                     KeyEvent {
@@ -123,33 +192,22 @@ impl ApplicationHandler<state::State> for App {
                     position.x / window_size.width as f64,
                     position.y / window_size.height as f64,
                 ];
+                app_state.mandelbrot_state.cursor_in_window = true;
 
-                // If we're dragging, pan the view
-                if app_state.mandelbrot_state.dragging {
-                    let delta_x = app_state.mandelbrot_state.cursor_location[0]
-                        - app_state.mandelbrot_state.prev_cursor_location[0];
-                    let delta_y = app_state.mandelbrot_state.cursor_location[1]
-                        - app_state.mandelbrot_state.prev_cursor_location[1];
-
-                    // Convert screen delta to complex plane delta
-                    let window_size = app_state.window.inner_size();
-                    let aspect_ratio = window_size.width as f32 / window_size.height as f32;
-                    let scale = 2.0 / app_state.mandelbrot_state.zoom;
-
-                    app_state.mandelbrot_state.center[0] -= delta_x as f32 * aspect_ratio * scale;
-                    app_state.mandelbrot_state.center[1] -= delta_y as f32 * scale; // Flip Y
-
-                    app_state.mandelbrot_state.needs_update = true;
-                    app_state.window.request_redraw();
-                }
+                app_state.pointer_moved();
+            }
+            WindowEvent::CursorLeft { .. } => {
+                app_state.mandelbrot_state.cursor_in_window = false;
             }
             WindowEvent::MouseInput {
                 device_id: _,
                 state,
                 button,
-            } => {
-                if button == MouseButton::Left {
-                    app_state.mandelbrot_state.dragging = state.is_pressed();
+            } if button == MouseButton::Left => {
+                if state.is_pressed() {
+                    app_state.pointer_pressed();
+                } else {
+                    app_state.pointer_released();
                 }
             }
             WindowEvent::MouseWheel {
@@ -157,27 +215,30 @@ impl ApplicationHandler<state::State> for App {
                 delta,
                 ..
             } => {
-                use winit::event::MouseScrollDelta;
-                let zoom_factor = match delta {
-                    MouseScrollDelta::LineDelta(_, y) => {
-                        if y > 0.0 {
-                            1.2
-                        } else {
-                            1.0 / 1.2
-                        }
-                    }
-                    MouseScrollDelta::PixelDelta(pos) => {
-                        if pos.y > 0.0 {
-                            1.1
-                        } else {
-                            1.0 / 1.1
-                        }
-                    }
+                let (pan_x, zoom_factor) = wheel_motion(
+                    delta,
+                    app_state.window.inner_size().width,
+                    app_state.modifiers.shift_key(),
+                );
+
+                if pan_x != 0.0 {
+                    // Positive deltas move the content right, the same as dragging right
+                    app_state.apply_command(Command::Pan { dx: pan_x, dy: 0.0 });
+                }
+
+                let Some(zoom_factor) = zoom_factor else {
+                    return;
                 };
 
-                app_state.mandelbrot_state.zoom *= zoom_factor;
-                app_state.mandelbrot_state.needs_update = true;
-                app_state.window.request_redraw();
+                // Ctrl+Alt+wheel stretches only the vertical axis
+                let command = if app_state.modifiers.control_key() && app_state.modifiers.alt_key()
+                {
+                    Command::StretchVertical(zoom_factor)
+                } else {
+                    // Keep the point under the cursor fixed
+                    Command::ZoomAtCursor(zoom_factor)
+                };
+                app_state.apply_command(command);
             }
             _ => {}
         }
@@ -185,8 +246,10 @@ impl ApplicationHandler<state::State> for App {
 }
 
 impl App {
+    /// Creates the app; the window and GPU state are set up on `resumed`.
+    /// On the web it needs the event loop to deliver the async GPU setup.
     pub fn new(
-        #[cfg(target_arch = "wasm32")] event_loop: &winit::event_loop::EventLoop<state::State>,
+        #[cfg(target_arch = "wasm32")] event_loop: &winit::event_loop::EventLoop<UserEvent>,
     ) -> Self {
         #[cfg(target_arch = "wasm32")]
         let proxy = Some(event_loop.create_proxy());
@@ -194,6 +257,287 @@ impl App {
             #[cfg(target_arch = "wasm32")]
             proxy,
             state: None,
+            retained: None,
+            retry: None,
+            #[cfg(target_arch = "wasm32")]
+            canvas_visible: true,
+            #[cfg(target_arch = "wasm32")]
+            page_visible: true,
+        }
+    }
+
+    /// Performs a command as if the matching input had been given. Does
+    /// nothing until the GPU state is ready.
+    pub fn apply_command(&mut self, command: Command) {
+        if let Some(state) = &mut self.state {
+            state.apply_command(command);
         }
     }
+
+    /// Creates the GPU state for `window`, carrying over anything retained
+    /// from a lost device. On the web it arrives later as `StateReady`.
+    fn create_state(&mut self, window: Arc<window::Window>) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            // If we are not on web we can use pollster to
+            // await the
+            match pollster::block_on(state::State::new(window.clone())) {
+                Ok(mut state) => {
+                    // Only the window's creation comes with a Resized event,
+                    // and a replacement or retried State has missed it
+                    let size = state.window.inner_size();
+                    state.resize(size.width, size.height);
+                    if let Some(retained) = self.retained.take() {
+                        state.restore(retained);
+                    }
+                    state.request_redraw();
+                    self.state = Some(state);
+                }
+                Err(e) => {
+                    log::error!(
+                        "Unable to create the GPU state, trying again in {:?}: {:#}",
+                        STATE_RETRY_INTERVAL,
+                        e
+                    );
+                    self.state_failed(window);
+                }
+            }
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        if let Some(proxy) = self.proxy.clone() {
+            wasm_bindgen_futures::spawn_local(async move {
+                let event = match state::State::new(window.clone()).await {
+                    Ok(state) => AppEvent::StateReady(Box::new(state)),
+                    Err(e) => {
+                        log::error!(
+                            "Unable to create the GPU state, trying again in {:?}: {:#}",
+                            STATE_RETRY_INTERVAL,
+                            e
+                        );
+                        AppEvent::StateFailed(window)
+                    }
+                };
+                assert!(proxy.send_event(UserEvent(event)).is_ok())
+            });
+        }
+    }
+
+    /// Leaves the window blank and schedules another attempt at creating
+    /// its State, which [`ApplicationHandler::about_to_wait`] makes.
+    fn state_failed(&mut self, window: Arc<window::Window>) {
+        self.retry = Some((window, Instant::now() + STATE_RETRY_INTERVAL));
+    }
+
+    /// Replaces a State whose device was lost with a fresh one. The view
+    /// and settings survive; paint doesn't.
+    fn recover_device(&mut self) {
+        let Some(lost) = self.state.take() else {
+            return;
+        };
+        log::warn!("Recreating GPU state after device loss; the painted canvas is lost");
+        let window = lost.window.clone();
+        // The old surface has to be gone before the window gets a new one
+        self.retained = Some(lost.into_retained());
+        self.create_state(window);
+    }
+
+    /// Rendering only runs while the canvas is on screen in a visible page.
+    #[cfg(target_arch = "wasm32")]
+    fn visible(&self) -> bool {
+        self.canvas_visible && self.page_visible
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn update_visibility(&mut self) {
+        let visible = self.visible();
+        if let Some(state) = &mut self.state {
+            state.set_visible(visible);
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for App {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Splits a wheel event into a horizontal pan, in normalized screen units
+/// like a drag, and a zoom factor. Horizontal scroll pans; vertical scroll
+/// zooms, and pure horizontal scrolls leave the zoom alone. Shift turns a
+/// plain wheel into horizontal scroll, unless the platform already has.
+fn wheel_motion(delta: MouseScrollDelta, window_width: u32, shift: bool) -> (f64, Option<f32>) {
+    let (x, y, pan_scale, zoom_step) = match delta {
+        MouseScrollDelta::LineDelta(x, y) => (x as f64, y as f64, LINE_SCROLL_PAN, 1.2),
+        MouseScrollDelta::PixelDelta(pos) => (pos.x, pos.y, 1.0 / window_width.max(1) as f64, 1.1),
+    };
+    let (x, y) = if shift && x == 0.0 { (y, 0.0) } else { (x, y) };
+    let zoom_factor = (y != 0.0).then(|| if y > 0.0 { zoom_step } else { 1.0 / zoom_step });
+    (x * pan_scale, zoom_factor)
+}
+
+// Anything less than this fraction of the canvas on screen counts as hidden
+#[cfg(target_arch = "wasm32")]
+const MIN_VISIBLE_RATIO: f64 = 0.05;
+
+/// Whether an intersection observer entry counts as the canvas being seen.
+#[cfg(target_arch = "wasm32")]
+fn canvas_visible(is_intersecting: bool, intersection_ratio: f64) -> bool {
+    is_intersecting && intersection_ratio >= MIN_VISIBLE_RATIO
+}
+
+/// Reports canvas and page visibility through the proxy so rendering can
+/// pause while nobody can see it.
+#[cfg(target_arch = "wasm32")]
+fn watch_visibility(
+    proxy: &winit::event_loop::EventLoopProxy<UserEvent>,
+    canvas: &web_sys::Element,
+) {
+    use wasm_bindgen::{JsCast, UnwrapThrowExt, closure::Closure};
+    use web_sys::{IntersectionObserver, IntersectionObserverEntry, IntersectionObserverInit};
+
+    let intersection_proxy = proxy.clone();
+    let on_intersect = Closure::<dyn FnMut(js_sys::Array)>::new(move |entries: js_sys::Array| {
+        // Entries are queued in order, so the last one is the current state
+        if let Some(entry) = entries.iter().last() {
+            let entry: IntersectionObserverEntry = entry.unchecked_into();
+            let visible = canvas_visible(entry.is_intersecting(), entry.intersection_ratio());
+            let _ = intersection_proxy.send_event(UserEvent(AppEvent::CanvasVisible(visible)));
+        }
+    });
+
+    let options = IntersectionObserverInit::new();
+    // Fire on both sides of the cutoff, and when the canvas fully leaves the screen
+    let thresholds = js_sys::Array::of2(&0.0.into(), &MIN_VISIBLE_RATIO.into());
+    options.set_threshold(&thresholds);
+    let observer =
+        IntersectionObserver::new_with_options(on_intersect.as_ref().unchecked_ref(), &options)
+            .unwrap_throw();
+    observer.observe(canvas);
+    // Both live for the whole page
+    on_intersect.forget();
+    std::mem::forget(observer);
+
+    let document = web_sys::window().unwrap_throw().document().unwrap_throw();
+    let visibility_proxy = proxy.clone();
+    let visibility_document = document.clone();
+    let on_visibility_change = Closure::<dyn FnMut()>::new(move || {
+        let _ = visibility_proxy.send_event(UserEvent(AppEvent::PageVisible(
+            !visibility_document.hidden(),
+        )));
+    });
+    document
+        .add_event_listener_with_callback(
+            "visibilitychange",
+            on_visibility_change.as_ref().unchecked_ref(),
+        )
+        .unwrap_throw();
+    on_visibility_change.forget();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::dpi::PhysicalPosition;
+
+    #[test]
+    fn vertical_wheel_zooms_and_horizontal_wheel_pans() {
+        assert_eq!(
+            wheel_motion(MouseScrollDelta::LineDelta(0.0, 1.0), 800, false),
+            (0.0, Some(1.2))
+        );
+        assert_eq!(
+            wheel_motion(MouseScrollDelta::LineDelta(0.0, -2.0), 800, false),
+            (0.0, Some(1.0 / 1.2))
+        );
+        assert_eq!(
+            wheel_motion(MouseScrollDelta::LineDelta(-1.0, 0.0), 800, false),
+            (-LINE_SCROLL_PAN, None)
+        );
+        assert_eq!(
+            wheel_motion(MouseScrollDelta::LineDelta(0.0, 0.0), 800, false),
+            (0.0, None)
+        );
+    }
+
+    #[test]
+    fn pixel_deltas_pan_by_the_window_width() {
+        let pixels = |x, y| MouseScrollDelta::PixelDelta(PhysicalPosition::new(x, y));
+        assert_eq!(wheel_motion(pixels(200.0, 0.0), 800, false), (0.25, None));
+        assert_eq!(
+            wheel_motion(pixels(-40.0, 3.0), 800, false),
+            (-0.05, Some(1.1))
+        );
+        assert_eq!(
+            wheel_motion(pixels(0.0, -3.0), 800, false),
+            (0.0, Some(1.0 / 1.1))
+        );
+        // A minimized window reports zero width
+        assert_eq!(wheel_motion(pixels(2.0, 0.0), 0, false), (2.0, None));
+    }
+
+    #[test]
+    fn shift_turns_the_wheel_sideways() {
+        assert_eq!(
+            wheel_motion(MouseScrollDelta::LineDelta(0.0, 1.0), 800, true),
+            (LINE_SCROLL_PAN, None)
+        );
+        let pixels = MouseScrollDelta::PixelDelta(PhysicalPosition::new(0.0, -80.0));
+        assert_eq!(wheel_motion(pixels, 800, true), (-0.1, None));
+        // Platforms that swap the axes themselves aren't swapped back
+        assert_eq!(
+            wheel_motion(MouseScrollDelta::LineDelta(1.0, 0.0), 800, true),
+            (LINE_SCROLL_PAN, None)
+        );
+    }
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod web_tests {
+    use super::*;
+    use wasm_bindgen::{JsCast, UnwrapThrowExt};
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn slivers_of_canvas_count_as_hidden() {
+        assert!(!canvas_visible(false, 0.0));
+        assert!(!canvas_visible(true, MIN_VISIBLE_RATIO / 2.0));
+        assert!(canvas_visible(true, MIN_VISIBLE_RATIO));
+        assert!(canvas_visible(true, 1.0));
+        // A stale ratio doesn't matter once the canvas stops intersecting
+        assert!(!canvas_visible(false, 1.0));
+    }
+
+    #[wasm_bindgen_test]
+    fn hidden_canvas_or_page_pauses_rendering() {
+        let event_loop = winit::event_loop::EventLoop::with_user_event()
+            .build()
+            .unwrap_throw();
+        let mut app = App::new(&event_loop);
+        assert!(app.visible());
+        app.canvas_visible = false;
+        assert!(!app.visible());
+        app.page_visible = false;
+        app.canvas_visible = true;
+        assert!(!app.visible());
+        app.page_visible = true;
+        assert!(app.visible());
+
+        // The observer and listener accept a real canvas and proxy
+        let document = web_sys::window().unwrap_throw().document().unwrap_throw();
+        let canvas: web_sys::Element = document
+            .create_element("canvas")
+            .unwrap_throw()
+            .unchecked_into();
+        document
+            .body()
+            .unwrap_throw()
+            .append_child(&canvas)
+            .unwrap_throw();
+        watch_visibility(&event_loop.create_proxy(), &canvas);
+    }
 }
@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+
+/// Number of bookmark slots, one per digit key 1 to 9.
+pub const SLOTS: usize = 9;
+
+#[cfg(target_arch = "wasm32")]
+const STORAGE_KEY: &str = "wgpu_paint.bookmarks";
+
+/// A saved place in the set.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub center: [f32; 2],
+    pub zoom: f32,
+    // Bookmarks saved before stretch was kept are unstretched
+    #[serde(default = "no_stretch")]
+    pub stretch: f32,
+    pub max_iterations: u32,
+}
+
+fn no_stretch() -> f32 {
+    1.0
+}
+
+/// The bookmark slots, kept in a JSON file in the config dir (local
+/// storage on the web) and written back whenever one changes.
+#[derive(Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Bookmarks {
+    slots: [Option<Bookmark>; SLOTS],
+}
+
+impl Bookmarks {
+    pub fn get(&self, slot: usize) -> Option<Bookmark> {
+        self.slots.get(slot).copied().flatten()
+    }
+
+    /// Stores `bookmark` in `slot`, replacing what was there.
+    pub fn set(&mut self, slot: usize, bookmark: Bookmark) {
+        if let Some(entry) = self.slots.get_mut(slot) {
+            *entry = Some(bookmark);
+        }
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /// The saved bookmarks, or none if there are none yet or they can't be
+    /// read.
+    pub fn load() -> Self {
+        match Self::read() {
+            Ok(Some(json)) => Self::from_json(&json).unwrap_or_else(|e| {
+                log::error!("Ignoring unreadable bookmarks: {}", e);
+                Self::default()
+            }),
+            Ok(None) => Self::default(),
+            Err(e) => {
+                log::error!("Unable to load bookmarks: {:#}", e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        Self::write(&self.to_json())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn path() -> anyhow::Result<std::path::PathBuf> {
+        let dir = dirs::config_dir().ok_or_else(|| anyhow::anyhow!("no config dir"))?;
+        Ok(dir.join("wgpu_paint").join("bookmarks.json"))
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn read() -> anyhow::Result<Option<String>> {
+        match std::fs::read_to_string(Self::path()?) {
+            Ok(json) => Ok(Some(json)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Writes to a temporary file first so a crash mid-write can't leave
+    /// truncated bookmarks behind.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn write(json: &str) -> anyhow::Result<()> {
+        let path = Self::path()?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let temp = path.with_extension("tmp");
+        std::fs::write(&temp, json)?;
+        std::fs::rename(&temp, &path)?;
+        Ok(())
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn storage() -> anyhow::Result<web_sys::Storage> {
+        web_sys::window()
+            .and_then(|window| window.local_storage().ok().flatten())
+            .ok_or_else(|| anyhow::anyhow!("no local storage"))
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn read() -> anyhow::Result<Option<String>> {
+        Self::storage()?
+            .get_item(STORAGE_KEY)
+            .map_err(|e| anyhow::anyhow!("{:?}", e))
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn write(json: &str) -> anyhow::Result<()> {
+        Self::storage()?
+            .set_item(STORAGE_KEY, json)
+            .map_err(|e| anyhow::anyhow!("{:?}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bookmark(zoom: f32) -> Bookmark {
+        Bookmark {
+            center: [-0.75, 0.1],
+            zoom,
+            stretch: 0.5,
+            max_iterations: 500,
+        }
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.set(0, bookmark(2.0));
+        bookmarks.set(SLOTS - 1, bookmark(1e6));
+        let loaded = Bookmarks::from_json(&bookmarks.to_json()).unwrap();
+        assert_eq!(loaded, bookmarks);
+        assert_eq!(loaded.get(0), Some(bookmark(2.0)));
+        assert_eq!(loaded.get(1), None);
+        assert_eq!(loaded.get(SLOTS - 1), Some(bookmark(1e6)));
+    }
+
+    #[test]
+    fn bookmarks_without_stretch_load_unstretched() {
+        let json = r#"{"slots": [{"center": [-0.75, 0.1], "zoom": 2.0, "max_iterations": 500},
+            null, null, null, null, null, null, null, null]}"#;
+        let loaded = Bookmarks::from_json(json).unwrap();
+        assert_eq!(
+            loaded.get(0),
+            Some(Bookmark {
+                stretch: 1.0,
+                ..bookmark(2.0)
+            })
+        );
+    }
+
+    #[test]
+    fn out_of_range_slots_are_ignored() {
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.set(SLOTS, bookmark(2.0));
+        assert_eq!(bookmarks, Bookmarks::default());
+        assert_eq!(bookmarks.get(SLOTS), None);
+    }
+
+    #[test]
+    fn malformed_json_is_an_error() {
+        assert!(Bookmarks::from_json("").is_err());
+        assert!(Bookmarks::from_json("{\"slots\": [").is_err());
+        // Valid JSON of the wrong shape
+        assert!(Bookmarks::from_json("{\"slots\": 3}").is_err());
+    }
+}
@@ -0,0 +1,67 @@
+struct BrushParams {
+    color: vec4<f32>,
+    // Stroke segment in texel coordinates
+    p0: vec2<f32>,
+    p1: vec2<f32>,
+    // Top-left of the dispatched region
+    origin: vec2<u32>,
+    radius: f32,
+    // Fraction of the radius painted at full strength; the rest feathers out
+    hardness: f32,
+    // Nonzero to erase to the empty canvas instead of painting color
+    erase: u32,
+}
+
+@group(0) @binding(0)
+var canvas_texture: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(1)
+var<uniform> brush: BrushParams;
+// Copy of the dispatched region of the canvas from before this segment
+@group(0) @binding(2)
+var previous_texture: texture_2d<f32>;
+
+fn distance_to_segment(p: vec2<f32>, a: vec2<f32>, b: vec2<f32>) -> f32 {
+    let ab = b - a;
+    let length_sq = dot(ab, ab);
+    var t = 0.0;
+    if length_sq > 0.0 {
+        t = clamp(dot(p - a, ab) / length_sq, 0.0, 1.0);
+    }
+    return distance(p, a + ab * t);
+}
+
+@compute @workgroup_size(8, 8)
+fn splat(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let xy = global_id.xy + brush.origin;
+    let dims = textureDimensions(canvas_texture);
+
+    // Check bounds
+    if xy.x >= dims.x || xy.y >= dims.y {
+        return;
+    }
+
+    // Sweep a disc along the segment so consecutive cursor events join up
+    let p = vec2<f32>(xy) + 0.5;
+    let d = distance_to_segment(p, brush.p0, brush.p1);
+    let inner = brush.radius * brush.hardness;
+    var coverage = 0.0;
+    if d <= inner {
+        coverage = 1.0;
+    } else if d < brush.radius {
+        coverage = 1.0 - smoothstep(inner, brush.radius, d);
+    }
+    if coverage <= 0.0 {
+        return;
+    }
+
+    var target_color = brush.color;
+    var strength = coverage * brush.color.a;
+    if brush.erase != 0u {
+        target_color = vec4<f32>(0.0);
+        strength = coverage;
+    }
+
+    let previous = textureLoad(previous_texture, vec2<i32>(xy), 0);
+    let color = mix(previous, target_color, strength);
+    textureStore(canvas_texture, vec2<i32>(xy), color);
+}
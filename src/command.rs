@@ -0,0 +1,109 @@
+use crate::state::Tool;
+
+/// Everything that can be done to the view or the canvas. Keyboard and
+/// mouse input are translated into these, and scripts or tests can feed
+/// them to [`App::apply_command`](crate::App::apply_command) directly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    /// Moves the view so this point of the complex plane is in the middle.
+    SetCenter([f64; 2]),
+    /// Sets the zoom; 1.0 shows the whole set.
+    SetZoom(f32),
+    /// Multiplies the zoom, keeping the center in place.
+    ZoomBy(f32),
+    /// Multiplies the zoom, keeping the point under the cursor in place.
+    ZoomAtCursor(f32),
+    /// Sets the iteration limit, leaving auto mode.
+    SetMaxIterations(u32),
+    /// Raises or lowers the iteration limit; in auto mode, the offset on
+    /// top of the automatic value.
+    AdjustIterations(i32),
+    /// Switches between a manual iteration limit and one derived from the
+    /// zoom.
+    ToggleAutoIterations,
+    /// Moves the content by a distance in normalized screen units, as if
+    /// dragged. Applied once per frame.
+    Pan {
+        /// Rightward motion as a fraction of the window width.
+        dx: f64,
+        /// Downward motion as a fraction of the window height.
+        dy: f64,
+    },
+    /// Back to the default center and zoom.
+    ResetView,
+    /// Multiplies the vertical stretch.
+    StretchVertical(f32),
+    /// Removes any vertical stretch.
+    ResetStretch,
+    /// Switches to the next present mode the surface supports.
+    CyclePresentMode,
+    /// Toggles preferring Mailbox (low latency) over Fifo (vsync) and
+    /// switches to the preferred mode.
+    ToggleLowLatency,
+    /// Toggles the frame time graph and title bar readout.
+    ToggleHud,
+    /// Toggles the iteration cost heatmap.
+    ToggleHeatmap,
+    /// Toggles outlines around the regions recent brush dispatches covered.
+    ToggleDirtyRects,
+    /// Toggles smooth escape-time coloring.
+    ToggleSmoothColoring,
+    /// Switches to the next built-in palette.
+    CyclePalette,
+    /// Selects what a left-button drag does.
+    SetTool(Tool),
+    /// Multiplies the brush radius.
+    ScaleBrushRadius(f32),
+    /// Adds to the brush hardness.
+    AdjustBrushHardness(f32),
+    /// Starts a stroke at the cursor with the current tool.
+    BeginStroke,
+    /// Extends the active stroke to the cursor.
+    ContinueStroke,
+    /// Finishes the active stroke.
+    EndStroke,
+    /// A single dab of the current tool at a normalized screen position, as
+    /// its own stroke.
+    PaintDot {
+        /// Horizontal position as a fraction of the window width.
+        x: f64,
+        /// Vertical position as a fraction of the window height.
+        y: f64,
+    },
+    /// Recomputes the fractal over the whole canvas, wiping all paint.
+    Clear,
+    /// Reverts the last stroke.
+    Undo,
+    /// Reapplies the last undone stroke.
+    Redo,
+    /// Saves the canvas for comparison.
+    TakeSnapshot,
+    /// Shows the comparison snapshot instead of the canvas, or stops.
+    ShowSnapshot(bool),
+    /// Starts or stops recording every fractal render to a CSV file
+    /// (desktop only).
+    ToggleSessionStats,
+    /// Copies present diagnostics to the clipboard.
+    CopyDiagnostics,
+    /// Centers the view on the nearest minibrot in sight, zoomed to fit.
+    SnapToMinibrot,
+    /// Goes back to the previous view, like a browser's back button.
+    HistoryBack,
+    /// Goes forward again after going back.
+    HistoryForward,
+    /// Remembers the center, zoom and iteration limit in a bookmark slot
+    /// (0 to 8), kept across runs.
+    SaveBookmark(usize),
+    /// Goes to the view saved in a bookmark slot, if there is one.
+    GoToBookmark(usize),
+    /// Saves what's on screen to a PNG file (desktop only).
+    Screenshot,
+    /// Renders the current view at any size and saves it to a PNG file
+    /// (desktop only).
+    Export {
+        /// Image width in pixels.
+        width: u32,
+        /// Image height in pixels.
+        height: u32,
+    },
+}
@@ -0,0 +1,393 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write as _};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+
+use crate::palette::PALETTES;
+
+// tEXt keyword the view settings are stored under in saved PNGs
+const SETTINGS_KEYWORD: &str = "wgpu_fractals";
+// Bumped whenever the settings text changes incompatibly
+const SETTINGS_VERSION: u32 = 1;
+// Longest settings text that gets parsed. What to_text writes is a few
+// hundred bytes, so anything longer isn't ours.
+const MAX_SETTINGS_LEN: usize = 1024;
+
+/// A buffer an RGBA8 texture region is copied into so the CPU can read it.
+pub struct Readback {
+    buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    // Rows in the buffer are padded to COPY_BYTES_PER_ROW_ALIGNMENT
+    padded_bytes_per_row: u32,
+}
+
+impl Readback {
+    /// Allocates room for a `width` x `height` RGBA8 region.
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let padded_bytes_per_row = (width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: padded_bytes_per_row as u64 * height as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        Self {
+            buffer,
+            width,
+            height,
+            padded_bytes_per_row,
+        }
+    }
+
+    /// Encodes a copy of the region at `origin` of `texture` into the buffer.
+    pub fn encode_copy(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        origin: [u32; 2],
+    ) {
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: origin[0],
+                    y: origin[1],
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &self.buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.padded_bytes_per_row),
+                    rows_per_image: Some(self.height),
+                },
+            },
+            wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Waits for the copy, encoded in `submission`, and returns the pixels
+    /// as tightly packed RGBA rows. Blocks until the GPU has finished that
+    /// submission, but not any later ones.
+    pub fn read(
+        self,
+        device: &wgpu::Device,
+        submission: wgpu::SubmissionIndex,
+    ) -> anyhow::Result<Vec<u8>> {
+        let slice = self.buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::PollType::WaitForSubmissionIndex(submission))?;
+        receiver
+            .recv()
+            .context("readback was never mapped")?
+            .context("unable to map readback buffer")?;
+
+        let row_bytes = (self.width * 4) as usize;
+        let mut pixels = Vec::with_capacity(row_bytes * self.height as usize);
+        {
+            let mapped = slice.get_mapped_range();
+            for row in mapped.chunks(self.padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..row_bytes]);
+            }
+        }
+        self.buffer.unmap();
+        Ok(pixels)
+    }
+}
+
+/// A strip of rows across an image, read back tile by tile.
+pub struct Strip {
+    height: u32,
+    // Each tile's left edge, its pixels and the submission copying them
+    tiles: Vec<(u32, Readback, wgpu::SubmissionIndex)>,
+}
+
+impl Strip {
+    pub fn new(height: u32) -> Self {
+        Self {
+            height,
+            tiles: Vec::new(),
+        }
+    }
+
+    /// Adds the tile at `x`, `height` rows tall, copied out by `submission`.
+    pub fn push(&mut self, x: u32, readback: Readback, submission: wgpu::SubmissionIndex) {
+        debug_assert_eq!(readback.height, self.height);
+        self.tiles.push((x, readback, submission));
+    }
+
+    /// Waits for every tile and stitches them into tightly packed RGBA rows
+    /// `width` pixels wide.
+    pub fn read(self, device: &wgpu::Device, width: u32) -> anyhow::Result<Vec<u8>> {
+        let row_bytes = width as usize * 4;
+        let mut pixels = vec![0u8; row_bytes * self.height as usize];
+        for (x, readback, submission) in self.tiles {
+            let tile_row_bytes = readback.width as usize * 4;
+            let tile_pixels = readback.read(device, submission)?;
+            for (row, tile_row) in tile_pixels.chunks_exact(tile_row_bytes).enumerate() {
+                let start = row * row_bytes + x as usize * 4;
+                pixels[start..start + tile_row_bytes].copy_from_slice(tile_row);
+            }
+        }
+        Ok(pixels)
+    }
+}
+
+/// Converts canvas pixels to what the display pass puts on screen. The
+/// display shows canvas values unchanged whatever the surface format, so
+/// only the alpha differs: the window is opaque.
+pub fn to_screen_colors(pixels: &mut [u8]) {
+    for pixel in pixels.chunks_exact_mut(4) {
+        pixel[3] = 255;
+    }
+}
+
+/// What produced a saved image, embedded in the PNG so the view can be
+/// recovered from the file later.
+#[derive(Debug, PartialEq)]
+pub struct ViewSettings {
+    pub center: [f32; 2],
+    pub zoom: f32,
+    pub stretch: f32,
+    pub max_iterations: u32,
+    pub palette: &'static str,
+    pub smooth_coloring: bool,
+    pub brush_color: [f32; 4],
+    pub brush_radius: f32,
+    pub brush_hardness: f32,
+}
+
+impl ViewSettings {
+    /// One `key=value` per line, starting with the format version.
+    fn to_text(&self) -> String {
+        let mut text = String::new();
+        let [r, g, b, a] = self.brush_color;
+        let _ = writeln!(text, "version={}", SETTINGS_VERSION);
+        let _ = writeln!(text, "center={},{}", self.center[0], self.center[1]);
+        let _ = writeln!(text, "zoom={}", self.zoom);
+        let _ = writeln!(text, "stretch={}", self.stretch);
+        let _ = writeln!(text, "max_iterations={}", self.max_iterations);
+        let _ = writeln!(text, "palette={}", self.palette);
+        let _ = writeln!(text, "smooth_coloring={}", self.smooth_coloring);
+        let _ = writeln!(text, "brush_color={},{},{},{}", r, g, b, a);
+        let _ = writeln!(text, "brush_radius={}", self.brush_radius);
+        let _ = writeln!(text, "brush_hardness={}", self.brush_hardness);
+        text
+    }
+
+    /// Reads back what [`ViewSettings::to_text`] wrote. None if the text is
+    /// from another version, too long, or missing or garbling a field, so
+    /// foreign and damaged chunks are ignored.
+    fn from_text(text: &str) -> Option<Self> {
+        if text.len() > MAX_SETTINGS_LEN {
+            return None;
+        }
+        let mut fields = HashMap::new();
+        for line in text.lines() {
+            let (key, value) = line.split_once('=')?;
+            // Which one counts would be a guess
+            if fields.insert(key, value).is_some() {
+                return None;
+            }
+        }
+        if fields.get("version")?.parse::<u32>().ok()? != SETTINGS_VERSION {
+            return None;
+        }
+
+        let float = |key| floats(fields.get(key)?).map(|[value]| value);
+        let palette = fields.get("palette")?;
+        let settings = Self {
+            center: floats(fields.get("center")?)?,
+            zoom: float("zoom")?,
+            stretch: float("stretch")?,
+            max_iterations: fields.get("max_iterations")?.parse().ok()?,
+            palette: PALETTES.iter().find(|p| p.name == *palette)?.name,
+            smooth_coloring: fields.get("smooth_coloring")?.parse().ok()?,
+            brush_color: floats(fields.get("brush_color")?)?,
+            brush_radius: float("brush_radius")?,
+            brush_hardness: float("brush_hardness")?,
+        };
+        // Ones that would leave nothing to see
+        let usable = settings.zoom > 0.0 && settings.stretch > 0.0 && settings.max_iterations > 0;
+        usable.then_some(settings)
+    }
+}
+
+// Exactly `N` comma-separated finite numbers
+fn floats<const N: usize>(text: &str) -> Option<[f32; N]> {
+    let mut parts = text.split(',');
+    let mut values = [0.0; N];
+    for value in &mut values {
+        *value = parts.next()?.parse().ok().filter(|v: &f32| v.is_finite())?;
+    }
+    parts.next().is_none().then_some(values)
+}
+
+/// The settings a PNG written by [`save_png`] or [`PngStream`] was made
+/// with. `Ok(None)` if it has none, or only ones [`ViewSettings::from_text`]
+/// rejects.
+pub fn read_settings(path: &Path) -> anyhow::Result<Option<ViewSettings>> {
+    let read = || -> anyhow::Result<_> {
+        let decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+        Ok(decoder.read_info()?)
+    };
+    let reader = read().with_context(|| format!("unable to read {}", path.display()))?;
+    Ok(reader
+        .info()
+        .uncompressed_latin1_text
+        .iter()
+        .filter(|chunk| chunk.keyword == SETTINGS_KEYWORD)
+        .find_map(|chunk| ViewSettings::from_text(&chunk.text)))
+}
+
+/// Writes tightly packed RGBA8 rows to a PNG file, with the settings that
+/// produced them in a tEXt chunk.
+pub fn save_png(
+    path: &Path,
+    width: u32,
+    height: u32,
+    pixels: &[u8],
+    settings: &ViewSettings,
+) -> anyhow::Result<()> {
+    let mut stream = PngStream::create(path, width, height, settings)?;
+    stream.write_rows(pixels)?;
+    stream.finish()
+}
+
+/// A PNG file written a few rows at a time, so an image never has to be
+/// in memory all at once.
+pub struct PngStream {
+    writer: png::StreamWriter<'static, BufWriter<File>>,
+    path: PathBuf,
+}
+
+impl PngStream {
+    /// Creates the file and writes everything up to the pixel data, with
+    /// `settings` in a tEXt chunk.
+    pub fn create(
+        path: &Path,
+        width: u32,
+        height: u32,
+        settings: &ViewSettings,
+    ) -> anyhow::Result<Self> {
+        let create = || -> anyhow::Result<_> {
+            let file = BufWriter::new(File::create(path)?);
+            let mut encoder = png::Encoder::new(file, width, height);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.add_text_chunk(SETTINGS_KEYWORD.to_string(), settings.to_text())?;
+            Ok(encoder.write_header()?.into_stream_writer()?)
+        };
+        let writer = create().with_context(|| format!("unable to write {}", path.display()))?;
+        Ok(Self {
+            writer,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Appends whole rows of tightly packed RGBA8 pixels.
+    pub fn write_rows(&mut self, rows: &[u8]) -> anyhow::Result<()> {
+        self.writer
+            .write_all(rows)
+            .with_context(|| format!("unable to write {}", self.path.display()))
+    }
+
+    /// Checks every row was written and flushes the file.
+    pub fn finish(self) -> anyhow::Result<()> {
+        let path = self.path;
+        self.writer
+            .finish()
+            .with_context(|| format!("unable to write {}", path.display()))
+    }
+}
+
+/// A file name in the working directory that won't collide with earlier
+/// ones, e.g. `screenshot-1760572800123.png`.
+pub fn timestamped_path(prefix: &str, extension: &str) -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis())
+        .unwrap_or_default();
+    PathBuf::from(format!("{}-{}.{}", prefix, millis, extension))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> ViewSettings {
+        ViewSettings {
+            center: [-0.743_643_9, 0.131_825_9],
+            zoom: 1234.5,
+            stretch: 0.5,
+            max_iterations: 800,
+            palette: PALETTES[2].name,
+            smooth_coloring: true,
+            brush_color: [0.1, 0.2, 0.3, 1.0],
+            brush_radius: 12.5,
+            brush_hardness: 0.25,
+        }
+    }
+
+    #[test]
+    fn settings_round_trip_through_text() {
+        let settings = settings();
+        assert_eq!(ViewSettings::from_text(&settings.to_text()), Some(settings));
+    }
+
+    #[test]
+    fn corrupt_or_foreign_settings_are_rejected() {
+        let text = settings().to_text();
+        let corrupt = [
+            String::new(),
+            "not settings at all".to_string(),
+            text.replace("version=1", "version=2"),
+            text.replace("version=1\n", ""),
+            text.replace("zoom=1234.5\n", ""),
+            text.replace("zoom=1234.5", "zoom=lots"),
+            text.replace("zoom=1234.5", "zoom=inf"),
+            text.replace("zoom=1234.5", "zoom=0"),
+            text.replace("stretch=0.5", "stretch=-0.5"),
+            text.replace("max_iterations=800", "max_iterations=-1"),
+            text.replace("palette=Grayscale", "palette=Plaid"),
+            text.replace("smooth_coloring=true", "smooth_coloring=yes"),
+            text.replace("brush_color=0.1,0.2,0.3,1", "brush_color=0.1,0.2,0.3"),
+            text.replace("brush_color=0.1,0.2,0.3,1", "brush_color=0.1,0.2,0.3,1,1"),
+            format!("{}zoom=2\n", text),
+            // Cut short partway through
+            text[..text.len() / 2].to_string(),
+            // Valid, but padded past the cap
+            format!("{}padding={}\n", text, "x".repeat(MAX_SETTINGS_LEN)),
+        ];
+        for text in corrupt {
+            assert_eq!(ViewSettings::from_text(&text), None, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn settings_round_trip_through_a_png() {
+        let path =
+            std::env::temp_dir().join(format!("wgpu_fractals-settings-{}.png", std::process::id()));
+        let settings = settings();
+        save_png(&path, 2, 1, &[255; 8], &settings).unwrap();
+        let read = read_settings(&path);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(read.unwrap(), Some(settings));
+    }
+}
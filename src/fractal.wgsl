@@ -0,0 +1,24 @@
+// Fragment path, for backends without compute shaders (WebGL2): appended
+// to mandelbrot.wgsl and drawn into the canvas as a render target. The
+// region a dispatch would cover is the scissor rect.
+
+// Fullscreen triangle, as in shader.wgsl
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    let corner = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_fractal(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    // Pixel centers are at .5, so this is the texel the compute path writes
+    let xy = vec2<u32>(position.xy);
+
+    // Already computed before the pan shifted it here; the pass loads the
+    // target, so leaving it alone keeps the copy
+    if reused(xy) {
+        discard;
+    }
+
+    return pixel_color(xy);
+}
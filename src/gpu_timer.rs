@@ -0,0 +1,202 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::Duration;
+
+use web_time::Instant;
+
+// Query indices: Mandelbrot pass begin/end, then display pass begin/end
+const QUERY_COUNT: u32 = 4;
+const RESOLVE_SIZE: u64 = QUERY_COUNT as u64 * std::mem::size_of::<u64>() as u64;
+// Readbacks in flight at once; a frame whose results have nowhere to go
+// just isn't timed
+const READBACK_SLOTS: usize = 3;
+// How often the timings are logged at debug level
+const LOG_INTERVAL: Duration = Duration::from_secs(1);
+
+// Slot states, shared with the map callback
+const FREE: u8 = 0;
+const MAPPING: u8 = 1;
+const MAPPED: u8 = 2;
+const FAILED: u8 = 3;
+
+/// GPU time spent in one frame's passes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GpuTimings {
+    /// Presents before the timed frame, as a frame number.
+    pub frame: u64,
+    /// The Mandelbrot pass, compute or fragment, if the frame ran one.
+    pub compute: Option<Duration>,
+    /// The display pass.
+    pub render: Duration,
+}
+
+struct Slot {
+    buffer: wgpu::Buffer,
+    state: Arc<AtomicU8>,
+    // Whether the frame resolved into this slot ran the Mandelbrot pass
+    computed: bool,
+    frame: u64,
+}
+
+/// Timestamp queries around the Mandelbrot and display passes, read back a
+/// frame or two later without stalling. Only exists when the device has
+/// `TIMESTAMP_QUERY`.
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    slots: Vec<Slot>,
+    // Slot the current frame resolved into, mapped once it's submitted
+    pending: Option<usize>,
+    // Nanoseconds per timestamp tick
+    period: f64,
+    latest: Option<GpuTimings>,
+    last_log: Option<Instant>,
+}
+
+impl GpuTimer {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Pass Timestamps"),
+            ty: wgpu::QueryType::Timestamp,
+            count: QUERY_COUNT,
+        });
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Timestamp Resolve Buffer"),
+            size: RESOLVE_SIZE,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let slots = (0..READBACK_SLOTS)
+            .map(|_| Slot {
+                buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Timestamp Readback Buffer"),
+                    size: RESOLVE_SIZE,
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                    mapped_at_creation: false,
+                }),
+                state: Arc::new(AtomicU8::new(FREE)),
+                computed: false,
+                frame: 0,
+            })
+            .collect();
+
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            slots,
+            pending: None,
+            period: queue.get_timestamp_period() as f64,
+            latest: None,
+            last_log: None,
+        })
+    }
+
+    pub fn compute_writes(&self) -> wgpu::ComputePassTimestampWrites<'_> {
+        wgpu::ComputePassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(0),
+            end_of_pass_write_index: Some(1),
+        }
+    }
+
+    /// The Mandelbrot pass on the fragment path, timed like the compute one.
+    pub fn fragment_writes(&self) -> wgpu::RenderPassTimestampWrites<'_> {
+        wgpu::RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(0),
+            end_of_pass_write_index: Some(1),
+        }
+    }
+
+    pub fn render_writes(&self) -> wgpu::RenderPassTimestampWrites<'_> {
+        wgpu::RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(2),
+            end_of_pass_write_index: Some(3),
+        }
+    }
+
+    /// Encodes copying this frame's timestamps out, after its passes.
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder, frame: u64, computed: bool) {
+        self.collect();
+        let Some(index) = self
+            .slots
+            .iter()
+            .position(|slot| slot.state.load(Ordering::Acquire) == FREE)
+        else {
+            return;
+        };
+
+        encoder.resolve_query_set(&self.query_set, 0..QUERY_COUNT, &self.resolve_buffer, 0);
+        let slot = &mut self.slots[index];
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &slot.buffer, 0, RESOLVE_SIZE);
+        slot.computed = computed;
+        slot.frame = frame;
+        slot.state.store(MAPPING, Ordering::Release);
+        self.pending = Some(index);
+    }
+
+    /// Starts reading back the frame just submitted.
+    pub fn after_submit(&mut self) {
+        let Some(index) = self.pending.take() else {
+            return;
+        };
+        let state = self.slots[index].state.clone();
+        self.slots[index]
+            .buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let next = if result.is_ok() { MAPPED } else { FAILED };
+                state.store(next, Ordering::Release);
+            });
+    }
+
+    /// The most recent frame's timings that have made it back from the GPU.
+    pub fn latest(&self) -> Option<GpuTimings> {
+        self.latest
+    }
+
+    // Reads any finished readbacks and frees their slots
+    fn collect(&mut self) {
+        for slot in &self.slots {
+            match slot.state.load(Ordering::Acquire) {
+                MAPPED => {
+                    let ticks: Vec<u64> = {
+                        let mapped = slot.buffer.slice(..).get_mapped_range();
+                        bytemuck::cast_slice(&mapped).to_vec()
+                    };
+                    slot.buffer.unmap();
+                    let elapsed = |begin: u64, end: u64| {
+                        Duration::from_nanos(
+                            (end.saturating_sub(begin) as f64 * self.period) as u64,
+                        )
+                    };
+                    self.latest = Some(GpuTimings {
+                        frame: slot.frame,
+                        compute: slot.computed.then(|| elapsed(ticks[0], ticks[1])),
+                        render: elapsed(ticks[2], ticks[3]),
+                    });
+                }
+                FAILED => {}
+                _ => continue,
+            }
+            slot.state.store(FREE, Ordering::Release);
+        }
+
+        let Some(timings) = self.latest else {
+            return;
+        };
+        let now = Instant::now();
+        if self
+            .last_log
+            .is_none_or(|last| now.duration_since(last) >= LOG_INTERVAL)
+        {
+            self.last_log = Some(now);
+            log::debug!("GPU timings: {:?}", timings);
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::VecDeque;
+
+use crate::pan_reuse::RenderedView;
+
+/// How many strokes can be undone before the oldest is forgotten.
+pub const MAX_UNDO_DEPTH: usize = 16;
+
+/// Undo/redo for painting, as copies of the whole canvas. Each undo entry is
+/// the canvas as it was before a stroke; each redo entry is the canvas an
+/// undo replaced. Entries remember the view they were rendered in, since
+/// panning or zooming recomputes the canvas: stepping back to an entry also
+/// steps back to its view. Textures are recycled through a free list, since
+/// they're all the canvas size.
+#[derive(Default)]
+pub struct CanvasHistory {
+    undo: VecDeque<Entry>,
+    redo: Vec<Entry>,
+    free: Vec<wgpu::Texture>,
+}
+
+// A saved canvas, and the view it shows if it matches one
+struct Entry {
+    texture: wgpu::Texture,
+    view: Option<RenderedView>,
+}
+
+/// What an undo or redo put back in the canvas.
+pub struct Restored {
+    /// The view the restored canvas was rendered in, if it matched one.
+    pub view: Option<RenderedView>,
+}
+
+impl CanvasHistory {
+    /// Saves `canvas`, rendered in `view`, as the state to return to on the
+    /// next undo. Call at the start of a stroke. Starting a new stroke
+    /// discards the redo stack.
+    pub fn record(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        canvas: &wgpu::Texture,
+        view: Option<RenderedView>,
+    ) {
+        let saved = self.capture(device, encoder, canvas, view);
+        if self.undo.len() == MAX_UNDO_DEPTH
+            && let Some(oldest) = self.undo.pop_front()
+        {
+            self.free.push(oldest.texture);
+        }
+        self.undo.push_back(saved);
+        self.free
+            .extend(self.redo.drain(..).map(|entry| entry.texture));
+    }
+
+    /// Restores the canvas from before the last stroke; `view` is the one
+    /// `canvas` is in now, for redo. Returns None if there is nothing to
+    /// undo.
+    pub fn undo(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        canvas: &wgpu::Texture,
+        view: Option<RenderedView>,
+    ) -> Option<Restored> {
+        let saved = self.undo.pop_back()?;
+        let replaced = self.capture(device, encoder, canvas, view);
+        self.redo.push(replaced);
+        Some(self.restore(encoder, saved, canvas))
+    }
+
+    /// Reapplies the last undone stroke; `view` is the one `canvas` is in
+    /// now, for undo. Returns None if there is nothing to redo.
+    pub fn redo(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        canvas: &wgpu::Texture,
+        view: Option<RenderedView>,
+    ) -> Option<Restored> {
+        let saved = self.redo.pop()?;
+        let replaced = self.capture(device, encoder, canvas, view);
+        self.undo.push_back(replaced);
+        Some(self.restore(encoder, saved, canvas))
+    }
+
+    /// Forgets everything and frees the textures, e.g. when the canvas
+    /// changes size and they no longer fit.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.free.clear();
+    }
+
+    // Copies the canvas into a free (or new) history texture
+    fn capture(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        canvas: &wgpu::Texture,
+        view: Option<RenderedView>,
+    ) -> Entry {
+        let texture = self.free.pop().unwrap_or_else(|| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("History Texture"),
+                size: canvas.size(),
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: canvas.format(),
+                usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            })
+        });
+        encoder.copy_texture_to_texture(
+            canvas.as_image_copy(),
+            texture.as_image_copy(),
+            canvas.size(),
+        );
+        Entry { texture, view }
+    }
+
+    // Copies a history texture back into the canvas and recycles it
+    fn restore(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        saved: Entry,
+        canvas: &wgpu::Texture,
+    ) -> Restored {
+        encoder.copy_texture_to_texture(
+            saved.texture.as_image_copy(),
+            canvas.as_image_copy(),
+            canvas.size(),
+        );
+        self.free.push(saved.texture);
+        Restored { view: saved.view }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::test_gpu;
+
+    // Canvases in these tests are a single pixel; its red channel tells
+    // them apart
+    fn fill(queue: &wgpu::Queue, canvas: &wgpu::Texture, level: u8) {
+        test_gpu::paint(queue, canvas, [0, 0], [level, 0, 0, 255]);
+    }
+
+    fn level(device: &wgpu::Device, queue: &wgpu::Queue, canvas: &wgpu::Texture) -> u8 {
+        let encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        test_gpu::read(device, queue, encoder, canvas)[0]
+    }
+
+    type Step = fn(
+        &mut CanvasHistory,
+        &wgpu::Device,
+        &mut wgpu::CommandEncoder,
+        &wgpu::Texture,
+        Option<RenderedView>,
+    ) -> Option<Restored>;
+
+    // Undoes or redoes, returning whether there was anything to step to
+    fn step(
+        history: &mut CanvasHistory,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        canvas: &wgpu::Texture,
+        step: Step,
+    ) -> bool {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        let restored = step(history, device, &mut encoder, canvas, None);
+        queue.submit([encoder.finish()]);
+        restored.is_some()
+    }
+
+    // A stroke turning the canvas from whatever it is to `level`
+    fn stroke(
+        history: &mut CanvasHistory,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        canvas: &wgpu::Texture,
+        level: u8,
+    ) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        history.record(device, &mut encoder, canvas, None);
+        queue.submit([encoder.finish()]);
+        fill(queue, canvas, level);
+    }
+
+    #[test]
+    #[cfg_attr(
+        not(feature = "gpu-tests"),
+        ignore = "needs a GPU, see --features gpu-tests"
+    )]
+    fn oldest_strokes_fall_off_past_the_depth_limit() {
+        let (device, queue) = test_gpu::device();
+        let canvas = test_gpu::canvas(&device, [1, 1]);
+        let mut history = CanvasHistory::default();
+        fill(&queue, &canvas, 0);
+        for level in 1..=MAX_UNDO_DEPTH as u8 + 1 {
+            stroke(&mut history, &device, &queue, &canvas, level);
+        }
+
+        let mut undone = 0;
+        while step(&mut history, &device, &queue, &canvas, CanvasHistory::undo) {
+            undone += 1;
+        }
+        assert_eq!(undone, MAX_UNDO_DEPTH);
+        // The canvas from before the first stroke is gone
+        assert_eq!(level(&device, &queue, &canvas), 1);
+
+        // Everything undone can be redone
+        let mut redone = 0;
+        while step(&mut history, &device, &queue, &canvas, CanvasHistory::redo) {
+            redone += 1;
+        }
+        assert_eq!(redone, MAX_UNDO_DEPTH);
+        assert_eq!(level(&device, &queue, &canvas), MAX_UNDO_DEPTH as u8 + 1);
+    }
+
+    #[test]
+    #[cfg_attr(
+        not(feature = "gpu-tests"),
+        ignore = "needs a GPU, see --features gpu-tests"
+    )]
+    fn a_new_stroke_clears_redo() {
+        let (device, queue) = test_gpu::device();
+        let canvas = test_gpu::canvas(&device, [1, 1]);
+        let mut history = CanvasHistory::default();
+        fill(&queue, &canvas, 0);
+        stroke(&mut history, &device, &queue, &canvas, 1);
+        stroke(&mut history, &device, &queue, &canvas, 2);
+
+        assert!(step(
+            &mut history,
+            &device,
+            &queue,
+            &canvas,
+            CanvasHistory::undo
+        ));
+        assert_eq!(level(&device, &queue, &canvas), 1);
+        stroke(&mut history, &device, &queue, &canvas, 3);
+        assert!(!step(
+            &mut history,
+            &device,
+            &queue,
+            &canvas,
+            CanvasHistory::redo
+        ));
+        assert_eq!(level(&device, &queue, &canvas), 3);
+
+        // Undo goes back through the new stroke, not the replaced one
+        assert!(step(
+            &mut history,
+            &device,
+            &queue,
+            &canvas,
+            CanvasHistory::undo
+        ));
+        assert_eq!(level(&device, &queue, &canvas), 1);
+        assert!(step(
+            &mut history,
+            &device,
+            &queue,
+            &canvas,
+            CanvasHistory::undo
+        ));
+        assert_eq!(level(&device, &queue, &canvas), 0);
+        assert!(!step(
+            &mut history,
+            &device,
+            &queue,
+            &canvas,
+            CanvasHistory::undo
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(
+        not(feature = "gpu-tests"),
+        ignore = "needs a GPU, see --features gpu-tests"
+    )]
+    fn entries_bring_back_their_view() {
+        let (device, queue) = test_gpu::device();
+        let canvas = test_gpu::canvas(&device, [1, 1]);
+        let mut history = CanvasHistory::default();
+        let view = |zoom| RenderedView {
+            center: [-0.5, 0.0],
+            zoom,
+            stretch: 1.0,
+            max_iterations: 100,
+            debug_heatmap: false,
+            smooth_coloring: false,
+            palette: 0,
+            size: [1, 1],
+        };
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        history.record(&device, &mut encoder, &canvas, Some(view(1.0)));
+        // Zoomed in since the stroke
+        let undone = history.undo(&device, &mut encoder, &canvas, Some(view(4.0)));
+        assert_eq!(undone.unwrap().view, Some(view(1.0)));
+        let redone = history.redo(&device, &mut encoder, &canvas, Some(view(1.0)));
+        assert_eq!(redone.unwrap().view, Some(view(4.0)));
+        queue.submit([encoder.finish()]);
+    }
+}
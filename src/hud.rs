@@ -0,0 +1,184 @@
+use std::time::Duration;
+
+use web_time::Instant;
+
+use crate::gpu_timer::GpuTimings;
+use crate::present_stats::PresentStats;
+
+// Frame time graph layout, in surface pixels from the bottom-left corner
+const GRAPH_MARGIN: u32 = 8;
+const BAR_WIDTH: u32 = 2;
+const MAX_BARS: usize = 120;
+const PIXELS_PER_MS: f64 = 4.0;
+const MAX_BAR_HEIGHT: u32 = 120;
+// How often the text readout in the window title changes
+const TITLE_INTERVAL: Duration = Duration::from_millis(500);
+// Leads the window title while the compare snapshot is shown
+const SNAPSHOT_MARKER: &str = "[SNAPSHOT] ";
+
+/// Display bind groups, each sampling a single texel of one color.
+pub struct HudColors {
+    pub on_time: wgpu::BindGroup,
+    pub missed: wgpu::BindGroup,
+    pub budget: wgpu::BindGroup,
+}
+
+/// F3 overlay: a bar graph of recent frame times drawn over the canvas,
+/// and FPS, GPU pass times, present mode, zoom and iterations in the
+/// window title. Costs nothing while hidden.
+pub struct Hud {
+    pub visible: bool,
+    // Whether the title should carry the compare snapshot marker
+    pub snapshot_marker: bool,
+    colors: HudColors,
+    // The title to put back when the HUD is hidden
+    saved_title: Option<String>,
+    last_title_update: Option<Instant>,
+}
+
+impl Hud {
+    pub fn new(colors: HudColors) -> Self {
+        Self {
+            visible: false,
+            snapshot_marker: false,
+            colors,
+            saved_title: None,
+            last_title_update: None,
+        }
+    }
+
+    pub fn toggle(&mut self, window: &winit::window::Window) {
+        self.visible = !self.visible;
+        if self.visible {
+            self.saved_title = Some(window.title());
+            self.last_title_update = None;
+        } else if let Some(title) = self.saved_title.take() {
+            window.set_title(&mark_snapshot(&title, self.snapshot_marker));
+        }
+    }
+
+    /// Refreshes the title readout, at most every [`TITLE_INTERVAL`].
+    pub fn update_title(
+        &mut self,
+        window: &winit::window::Window,
+        stats: &PresentStats,
+        gpu_timings: Option<GpuTimings>,
+        present_mode: wgpu::PresentMode,
+        zoom: f32,
+        max_iterations: u32,
+    ) {
+        if !self.visible {
+            return;
+        }
+        let now = Instant::now();
+        if self
+            .last_title_update
+            .is_some_and(|last| now.duration_since(last) < TITLE_INTERVAL)
+        {
+            return;
+        }
+        self.last_title_update = Some(now);
+
+        let frame = match stats.average_interval() {
+            Some(interval) => format!(
+                "{:.1} fps ({:.2} ms)",
+                1.0 / interval.as_secs_f64(),
+                interval.as_secs_f64() * 1000.0
+            ),
+            None => "idle".to_string(),
+        };
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let gpu = match gpu_timings {
+            Some(GpuTimings {
+                compute: Some(compute),
+                render,
+                ..
+            }) => format!(
+                " | compute {:.2} ms, display {:.2} ms",
+                ms(compute),
+                ms(render)
+            ),
+            Some(GpuTimings {
+                compute: None,
+                render,
+                ..
+            }) => format!(" | display {:.2} ms", ms(render)),
+            None => String::new(),
+        };
+        let title = format!(
+            "{}{} | {:?} | zoom {:.3e} | {} iterations",
+            frame, gpu, present_mode, zoom, max_iterations
+        );
+        window.set_title(&mark_snapshot(&title, self.snapshot_marker));
+    }
+
+    /// Draws the frame time graph with the display pipeline, which must
+    /// already be set on `render_pass`. Bars that missed vsync are red; the
+    /// white line is the monitor's frame budget.
+    pub fn draw(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        stats: &PresentStats,
+        surface_size: [u32; 2],
+    ) {
+        if !self.visible {
+            return;
+        }
+        let [width, height] = surface_size;
+        let graph_width = MAX_BARS as u32 * BAR_WIDTH;
+        if width < graph_width + 2 * GRAPH_MARGIN || height < MAX_BAR_HEIGHT + 2 * GRAPH_MARGIN {
+            return;
+        }
+        let baseline = height - GRAPH_MARGIN;
+        let bar_height = |interval: Duration| {
+            ((interval.as_secs_f64() * 1000.0 * PIXELS_PER_MS) as u32).clamp(1, MAX_BAR_HEIGHT)
+        };
+
+        // Newest bar on the right
+        for (i, (interval, missed)) in stats.recent_intervals().rev().take(MAX_BARS).enumerate() {
+            let h = bar_height(interval);
+            let x = GRAPH_MARGIN + graph_width - (i as u32 + 1) * BAR_WIDTH;
+            let color = if missed {
+                &self.colors.missed
+            } else {
+                &self.colors.on_time
+            };
+            render_pass.set_bind_group(0, color, &[]);
+            render_pass.set_scissor_rect(x, baseline - h, BAR_WIDTH, h);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        if let Some(period) = stats.monitor_period() {
+            let h = bar_height(period);
+            render_pass.set_bind_group(0, &self.colors.budget, &[]);
+            render_pass.set_scissor_rect(GRAPH_MARGIN, baseline - h, graph_width, 1);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+}
+
+/// `title` with the snapshot marker in front while the compare snapshot is
+/// shown, and without it otherwise.
+pub fn mark_snapshot(title: &str, showing_snapshot: bool) -> String {
+    let title = title.strip_prefix(SNAPSHOT_MARKER).unwrap_or(title);
+    if showing_snapshot {
+        format!("{}{}", SNAPSHOT_MARKER, title)
+    } else {
+        title.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_marker_comes_and_goes() {
+        let marked = mark_snapshot("wgpu_paint", true);
+        assert_eq!(marked, "[SNAPSHOT] wgpu_paint");
+        // Marking twice doesn't stack markers
+        assert_eq!(mark_snapshot(&marked, true), marked);
+        assert_eq!(mark_snapshot(&marked, false), "wgpu_paint");
+        assert_eq!(mark_snapshot("wgpu_paint", false), "wgpu_paint");
+    }
+}
@@ -1,13 +1,47 @@
+//! Real-time Mandelbrot explorer built on wgpu and winit.
+//!
+//! Most users just call [`run`]. Embedders that want to drive the event loop
+//! themselves should import from [`prelude`], which is the supported API;
+//! everything else is internal and may change between releases.
+#![deny(missing_docs)]
+
 mod app;
-mod shader;
+mod bookmarks;
+mod command;
+#[cfg(not(target_arch = "wasm32"))]
+mod export;
+mod gpu_timer;
+mod history;
+mod hud;
+mod minibrot;
+mod palette;
+mod pan_reuse;
+mod pipeline_cache;
+mod present_stats;
+mod render_path;
+#[cfg(not(target_arch = "wasm32"))]
+mod session_stats;
 mod state;
-pub use shader::Vertex;
+mod submit_log;
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod test_gpu;
+mod view_history;
 
-pub use app::App;
+pub use app::{App, UserEvent};
+pub use command::Command;
+pub use state::Tool;
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 use winit::event_loop::EventLoop;
 
+/// The supported public API, for `use wgpu_fractals::prelude::*`.
+pub mod prelude {
+    #[cfg(target_arch = "wasm32")]
+    pub use crate::run_web;
+    pub use crate::{App, Command, Tool, UserEvent, run};
+}
+
+/// Opens a window and runs the explorer until it is closed.
 pub fn run() -> anyhow::Result<()> {
     #[cfg(not(target_arch = "wasm32"))]
     {
@@ -27,6 +61,8 @@ pub fn run() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Web entry point, called automatically when the wasm module starts.
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen(start)]
 pub fn run_web() -> Result<(), wasm_bindgen::JsValue> {
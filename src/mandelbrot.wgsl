@@ -0,0 +1,160 @@
+// Shared by the compute and fragment paths; each file is appended to
+// this one to make a shader module.
+
+struct MandelbrotParams {
+    center: vec2<f32>,
+    zoom: f32,
+    max_iterations: u32,
+    debug_heatmap: u32,
+    // Extra vertical magnification (anamorphic view)
+    stretch: f32,
+    // Top-left of the dispatched region
+    origin: vec2<u32>,
+    // Pixels inside [reuse_min, reuse_max) were kept from the last frame
+    reuse_min: vec2<u32>,
+    reuse_max: vec2<u32>,
+    // Continuous (log-log) escape time instead of whole iteration counts
+    smooth_coloring: u32,
+    _padding0: u32,
+    // Where the output texture sits in the whole image, and the whole
+    // image's size. Only differs from the texture for tiled exports.
+    tile_offset: vec2<u32>,
+    image_size: vec2<u32>,
+    _padding1: vec2<u32>,
+}
+
+// Bailout |z|^2 for smooth coloring. Escaping far past 2 makes the
+// fractional part accurate enough that bands don't show.
+const SMOOTH_BAILOUT: f32 = 65536.0;
+
+struct Escape {
+    // Step at which z escaped, or max_iter if it never did
+    iterations: u32,
+    // |z|^2 at escape
+    magnitude_sq: f32,
+}
+
+// Must match palette::MAX_STOPS
+const MAX_STOPS: u32 = 8u;
+
+struct GradientStop {
+    color: vec4<f32>,
+    position: f32,
+}
+
+struct Palette {
+    stops: array<GradientStop, MAX_STOPS>,
+    count: u32,
+}
+
+@group(0) @binding(1)
+var<uniform> params: MandelbrotParams;
+@group(0) @binding(2)
+var<uniform> palette: Palette;
+
+fn mandelbrot_iterations(c: vec2<f32>, max_iter: u32, bailout: f32) -> Escape {
+    var z = vec2<f32>(0.0, 0.0);
+    var escape = Escape(max_iter, 0.0);
+
+    for (var i = 0u; i < max_iter; i = i + 1u) {
+        // z = z^2 + c
+        let z_real = z.x * z.x - z.y * z.y + c.x;
+        let z_imag = 2.0 * z.x * z.y + c.y;
+        z = vec2<f32>(z_real, z_imag);
+
+        // Check if |z|^2 > bailout (diverged)
+        let magnitude_sq = z.x * z.x + z.y * z.y;
+        if magnitude_sq > bailout {
+            escape = Escape(i, magnitude_sq);
+            break;
+        }
+    }
+
+    return escape;
+}
+
+// Fractional escape time: i + 1 - log2(log|z|), continuous across the
+// boundaries where the integer count jumps
+fn smooth_iterations(escape: Escape) -> f32 {
+    let log_magnitude = 0.5 * log(escape.magnitude_sq);
+    return f32(escape.iterations) + 1.0 - log2(log_magnitude);
+}
+
+// Linearly interpolates t through the palette's gradient stops
+fn palette_color(t: f32) -> vec4<f32> {
+    if palette.count == 0u {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+
+    var color = palette.stops[0].color;
+    for (var i = 1u; i < palette.count; i = i + 1u) {
+        let prev = palette.stops[i - 1u];
+        let next = palette.stops[i];
+        if t <= next.position {
+            let span = max(next.position - prev.position, 1e-6);
+            let f = clamp((t - prev.position) / span, 0.0, 1.0);
+            return mix(prev.color, next.color, f);
+        }
+        color = next.color;
+    }
+
+    return color;
+}
+
+// Debug palette for the cost heatmap: black -> blue -> red -> yellow -> white
+fn heat_color(t: f32) -> vec3<f32> {
+    let x = clamp(t, 0.0, 1.0) * 4.0;
+    if x < 1.0 {
+        return vec3<f32>(0.0, 0.0, x);
+    } else if x < 2.0 {
+        return vec3<f32>(x - 1.0, 0.0, 2.0 - x);
+    } else if x < 3.0 {
+        return vec3<f32>(1.0, x - 2.0, 0.0);
+    }
+    return vec3<f32>(1.0, 1.0, x - 3.0);
+}
+
+// Whether pixel xy was kept from the last frame by a pan
+fn reused(xy: vec2<u32>) -> bool {
+    return all(xy >= params.reuse_min) && all(xy < params.reuse_max);
+}
+
+// Color of pixel xy of the output texture
+fn pixel_color(xy: vec2<u32>) -> vec4<f32> {
+    // Convert pixel coordinates to complex plane coordinates
+    let pixel_pos = vec2<f32>(xy + params.tile_offset);
+    let dims_f = vec2<f32>(params.image_size);
+
+    // Normalize to [-1, 1] and then scale by zoom around center
+    let normalized = (pixel_pos / dims_f) * 2.0 - 1.0;
+    let aspect_ratio = dims_f.x / dims_f.y;
+    let scaled = vec2<f32>(normalized.x * aspect_ratio, normalized.y)
+        / (params.zoom * vec2<f32>(1.0, params.stretch));
+    let c = scaled + params.center;
+
+    // Calculate Mandelbrot iterations
+    var bailout = 4.0;
+    if params.smooth_coloring != 0u {
+        bailout = SMOOTH_BAILOUT;
+    }
+    let escape = mandelbrot_iterations(c, params.max_iterations, bailout);
+    let iterations = escape.iterations;
+
+    var color = vec4<f32>(0.0, 0.0, 0.0, 1.0);
+
+    if params.debug_heatmap != 0u {
+        // Iterations actually executed: escaping at step i ran i + 1 of them
+        let executed = min(iterations + 1u, params.max_iterations);
+        let cost = f32(executed) / f32(params.max_iterations);
+        color = vec4<f32>(heat_color(cost), 1.0);
+    } else if iterations < params.max_iterations {
+        // Color based on iteration count through the palette
+        var escape_time = f32(iterations);
+        if params.smooth_coloring != 0u {
+            escape_time = max(smooth_iterations(escape), 0.0);
+        }
+        color = palette_color(escape_time / f32(params.max_iterations));
+    }
+
+    return color;
+}
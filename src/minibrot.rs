@@ -0,0 +1,209 @@
+use std::ops::{Add, Div, Mul, Sub};
+
+// Newton steps per candidate period before giving up on it
+const MAX_NEWTON_STEPS: usize = 64;
+// Relative step size at which Newton's method counts as converged
+const NEWTON_TOLERANCE: f64 = 1e-14;
+
+/// A minibrot, one of the small copies of the set, found near a point.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Minibrot {
+    /// The nucleus: the point whose critical orbit has period `period`.
+    pub center: [f64; 2],
+    /// Iterations the critical orbit takes to return to 0.
+    pub period: u32,
+    /// Roughly the minibrot's size relative to the whole set.
+    pub size: f64,
+}
+
+/// Finds the minibrot nearest `seed` whose nucleus is within `radius` of
+/// it. Candidate periods come from the orbit of `seed` itself, each time
+/// it gets closer to 0 than before, and are tried deepest first with
+/// Newton's method on `z_period(c) = 0`.
+pub fn find_nearby(seed: [f64; 2], radius: f64, max_iterations: u32) -> Option<Minibrot> {
+    let seed = Complex::from(seed);
+    candidate_periods(seed, max_iterations)
+        .into_iter()
+        .rev()
+        .find_map(|period| {
+            let nucleus = find_nucleus(seed, period)?;
+            let period = exact_period(nucleus, period);
+            ((nucleus - seed).norm_sqr() <= radius * radius).then(|| Minibrot {
+                center: [nucleus.re, nucleus.im],
+                period,
+                size: size_estimate(nucleus, period),
+            })
+        })
+}
+
+/// Iterations at which the orbit of `c` comes closer to 0 than at any
+/// earlier one. Each marks an atom domain containing `c`, and so a
+/// period worth searching.
+fn candidate_periods(c: Complex, max_iterations: u32) -> Vec<u32> {
+    let mut periods = Vec::new();
+    let mut z = Complex::ZERO;
+    let mut closest = f64::INFINITY;
+    for period in 1..=max_iterations {
+        z = z * z + c;
+        let distance = z.norm_sqr();
+        if distance > 4.0 {
+            break;
+        }
+        if distance < closest {
+            closest = distance;
+            periods.push(period);
+        }
+    }
+    periods
+}
+
+fn find_nucleus(seed: Complex, period: u32) -> Option<Complex> {
+    let mut c = seed;
+    for _ in 0..MAX_NEWTON_STEPS {
+        // z_period(c) and its derivative with respect to c
+        let mut z = Complex::ZERO;
+        let mut dz = Complex::ZERO;
+        for _ in 0..period {
+            dz = Complex::new(2.0, 0.0) * z * dz + Complex::new(1.0, 0.0);
+            z = z * z + c;
+        }
+        if dz.norm_sqr() == 0.0 {
+            return None;
+        }
+
+        let step = z / dz;
+        c = c - step;
+        if !c.re.is_finite() || !c.im.is_finite() {
+            return None;
+        }
+        let tolerance = NEWTON_TOLERANCE * (1.0 + c.norm_sqr().sqrt());
+        if step.norm_sqr() <= tolerance * tolerance {
+            return Some(c);
+        }
+    }
+    None
+}
+
+// Newton's method for period p also converges to nuclei of periods that
+// divide p; the orbit returning to 0 early gives those away
+fn exact_period(nucleus: Complex, period: u32) -> u32 {
+    let scale = 1.0 + nucleus.norm_sqr().sqrt();
+    let mut z = Complex::ZERO;
+    for k in 1..period {
+        z = z * z + nucleus;
+        if z.norm_sqr().sqrt() <= NEWTON_TOLERANCE.sqrt() * scale {
+            return k;
+        }
+    }
+    period
+}
+
+// The usual estimate from the orbit's derivative; the main cardioid
+// (period 1) comes out as 1.0
+fn size_estimate(nucleus: Complex, period: u32) -> f64 {
+    let mut z = Complex::ZERO;
+    let mut l = Complex::new(1.0, 0.0);
+    let mut b = Complex::new(1.0, 0.0);
+    for _ in 1..period {
+        z = z * z + nucleus;
+        l = Complex::new(2.0, 0.0) * z * l;
+        b = b + Complex::new(1.0, 0.0) / l;
+    }
+    1.0 / (b * l * l).norm_sqr().sqrt()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    const ZERO: Self = Self::new(0.0, 0.0);
+
+    const fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    fn norm_sqr(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+}
+
+impl From<[f64; 2]> for Complex {
+    fn from([re, im]: [f64; 2]) -> Self {
+        Self::new(re, im)
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        let denominator = other.norm_sqr();
+        Self::new(
+            (self.re * other.re + self.im * other.im) / denominator,
+            (self.im * other.re - self.re * other.im) / denominator,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Nucleus of the period-3 minibrot on the real axis
+    const PERIOD_3_NUCLEUS: f64 = -1.754877666;
+
+    #[test]
+    fn finds_the_period_3_minibrot() {
+        let minibrot = find_nearby([-1.7549, 0.0], 0.01, 1000).unwrap();
+        assert_eq!(minibrot.period, 3);
+        assert!((minibrot.center[0] - PERIOD_3_NUCLEUS).abs() < 1e-9);
+        assert!(minibrot.center[1].abs() < 1e-9);
+        assert!(minibrot.size > 0.0 && minibrot.size < 0.1);
+    }
+
+    #[test]
+    fn nothing_outside_the_radius() {
+        assert_eq!(find_nearby([-1.7549, 0.0], 1e-6, 1000), None);
+    }
+
+    #[test]
+    fn exact_period_reduces_a_divisor_period() {
+        let nucleus = find_nucleus(Complex::new(-1.7549, 0.0), 3).unwrap();
+        assert_eq!(exact_period(nucleus, 3), 3);
+        // The period-3 nucleus is also a root of z_6 and z_9
+        assert_eq!(exact_period(nucleus, 6), 3);
+        assert_eq!(exact_period(nucleus, 9), 3);
+        // The main cardioid's nucleus returns to 0 at once
+        assert_eq!(exact_period(Complex::ZERO, 4), 1);
+    }
+}
@@ -0,0 +1,101 @@
+/// Most gradient stops a palette can have. Must match compute.wgsl.
+pub const MAX_STOPS: usize = 8;
+
+/// A color gradient the escape time is mapped through, as stops of
+/// (position in [0, 1], RGBA) in increasing position order.
+#[derive(Clone, Copy, Debug)]
+pub struct Palette {
+    pub name: &'static str,
+    pub stops: &'static [(f32, [f32; 4])],
+}
+
+/// The built-in palettes, in cycling order. The first one is the default.
+pub const PALETTES: &[Palette] = &[
+    // Linear RGB between the primaries and secondaries is exactly the
+    // fully saturated HSV hue wheel the shader used to compute
+    Palette {
+        name: "Rainbow",
+        stops: &[
+            (0.0, [1.0, 0.0, 0.0, 1.0]),
+            (1.0 / 6.0, [1.0, 1.0, 0.0, 1.0]),
+            (2.0 / 6.0, [0.0, 1.0, 0.0, 1.0]),
+            (3.0 / 6.0, [0.0, 1.0, 1.0, 1.0]),
+            (4.0 / 6.0, [0.0, 0.0, 1.0, 1.0]),
+            (5.0 / 6.0, [1.0, 0.0, 1.0, 1.0]),
+            (1.0, [1.0, 0.0, 0.0, 1.0]),
+        ],
+    },
+    Palette {
+        name: "Classic",
+        stops: &[
+            (0.0, [0.0, 0.027, 0.392, 1.0]),
+            (0.16, [0.125, 0.42, 0.796, 1.0]),
+            (0.42, [0.929, 1.0, 1.0, 1.0]),
+            (0.6425, [1.0, 0.667, 0.0, 1.0]),
+            (0.8575, [0.0, 0.008, 0.0, 1.0]),
+            (1.0, [0.0, 0.027, 0.392, 1.0]),
+        ],
+    },
+    Palette {
+        name: "Grayscale",
+        stops: &[(0.0, [0.0, 0.0, 0.0, 1.0]), (1.0, [1.0, 1.0, 1.0, 1.0])],
+    },
+    Palette {
+        name: "Fire",
+        stops: &[
+            (0.0, [0.0, 0.0, 0.0, 1.0]),
+            (0.33, [0.5, 0.0, 0.0, 1.0]),
+            (0.66, [1.0, 0.5, 0.0, 1.0]),
+            (0.9, [1.0, 1.0, 0.3, 1.0]),
+            (1.0, [1.0, 1.0, 1.0, 1.0]),
+        ],
+    },
+    Palette {
+        name: "Viridis",
+        stops: &[
+            (0.0, [0.267, 0.005, 0.329, 1.0]),
+            (0.25, [0.229, 0.322, 0.546, 1.0]),
+            (0.5, [0.128, 0.567, 0.551, 1.0]),
+            (0.75, [0.369, 0.789, 0.383, 1.0]),
+            (1.0, [0.993, 0.906, 0.144, 1.0]),
+        ],
+    },
+];
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct GradientStop {
+    color: [f32; 4],
+    position: f32,
+    // Uniform array elements are 16-byte aligned
+    _padding: [f32; 3],
+}
+
+/// GPU layout of a palette, bound next to the fractal params.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PaletteUniform {
+    stops: [GradientStop; MAX_STOPS],
+    count: u32,
+    _padding: [u32; 3],
+}
+
+impl Palette {
+    /// Packs the stops for upload, dropping any past [`MAX_STOPS`].
+    pub fn to_uniform(self) -> PaletteUniform {
+        let mut uniform = PaletteUniform {
+            stops: [GradientStop {
+                color: [0.0; 4],
+                position: 0.0,
+                _padding: [0.0; 3],
+            }; MAX_STOPS],
+            count: self.stops.len().min(MAX_STOPS) as u32,
+            _padding: [0; 3],
+        };
+        for (stop, &(position, color)) in uniform.stops.iter_mut().zip(self.stops) {
+            stop.color = color;
+            stop.position = position;
+        }
+        uniform
+    }
+}
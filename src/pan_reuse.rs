@@ -0,0 +1,289 @@
+/// The view the canvas currently holds, so small pans can reuse most of it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RenderedView {
+    pub center: [f32; 2],
+    pub zoom: f32,
+    pub stretch: f32,
+    pub max_iterations: u32,
+    pub debug_heatmap: bool,
+    pub smooth_coloring: bool,
+    pub palette: usize,
+    pub size: [u32; 2],
+}
+
+impl RenderedView {
+    /// Size of one pixel in the complex plane along each axis. Pixels are
+    /// square unless the view is stretched vertically.
+    pub fn pixel_size(&self) -> [f32; 2] {
+        let width = 2.0 / (self.zoom * self.size[1] as f32);
+        [width, width / self.stretch]
+    }
+}
+
+/// A pan that can be served by shifting the previous image.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PanReuse {
+    /// Whole pixels the image content moves by.
+    pub shift: [i32; 2],
+    /// The new center snapped to the previous image's pixel grid. The
+    /// sub-pixel remainder stays in the logical center and is picked up by
+    /// the next pan.
+    pub center: [f32; 2],
+}
+
+/// Decides whether going from `prev` to `next` is a pure pan of less than a
+/// screen, and if so by how many pixels.
+pub fn plan_pan_reuse(prev: &RenderedView, next: &RenderedView) -> Option<PanReuse> {
+    if prev.zoom != next.zoom
+        || prev.stretch != next.stretch
+        || prev.max_iterations != next.max_iterations
+        || prev.debug_heatmap != next.debug_heatmap
+        || prev.smooth_coloring != next.smooth_coloring
+        || prev.palette != next.palette
+        || prev.size != next.size
+    {
+        return None;
+    }
+
+    let pixel_size = prev.pixel_size();
+    let mut shift = [0i32; 2];
+    let mut center = prev.center;
+    for axis in 0..2 {
+        let moved = ((next.center[axis] - prev.center[axis]) / pixel_size[axis]).round();
+        if !moved.is_finite() || moved.abs() >= prev.size[axis] as f32 {
+            return None;
+        }
+        // Moving the center right moves the content left
+        shift[axis] = -(moved as i32);
+        center[axis] = prev.center[axis] + moved * pixel_size[axis];
+    }
+
+    Some(PanReuse { shift, center })
+}
+
+/// The pieces of a shifted image: what to copy and what to recompute.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ShiftRegions {
+    /// Top-left of the kept area in the old image.
+    pub copy_src: [u32; 2],
+    /// Top-left of the kept area in the new image.
+    pub copy_dst: [u32; 2],
+    /// Size of the kept area.
+    pub copy_size: [u32; 2],
+    /// Bounding box of the newly exposed pixels that need computing.
+    pub dispatch_origin: [u32; 2],
+    pub dispatch_size: [u32; 2],
+}
+
+/// Splits an image of `size` shifted by `shift` into the kept region and the
+/// bounding box of the exposed strips.
+pub fn shift_regions(size: [u32; 2], shift: [i32; 2]) -> ShiftRegions {
+    let mut regions = ShiftRegions {
+        copy_src: [0; 2],
+        copy_dst: [0; 2],
+        copy_size: [0; 2],
+        dispatch_origin: [0; 2],
+        dispatch_size: size,
+    };
+
+    for axis in 0..2 {
+        let amount = shift[axis].unsigned_abs().min(size[axis]);
+        regions.copy_size[axis] = size[axis] - amount;
+        if shift[axis] >= 0 {
+            regions.copy_dst[axis] = amount;
+        } else {
+            regions.copy_src[axis] = amount;
+        }
+    }
+
+    // Only one axis moved: the exposed area is a single strip
+    match (shift[0] != 0, shift[1] != 0) {
+        (true, false) => {
+            regions.dispatch_size[0] = size[0] - regions.copy_size[0];
+            if shift[0] < 0 {
+                regions.dispatch_origin[0] = regions.copy_size[0];
+            }
+        }
+        (false, true) => {
+            regions.dispatch_size[1] = size[1] - regions.copy_size[1];
+            if shift[1] < 0 {
+                regions.dispatch_origin[1] = regions.copy_size[1];
+            }
+        }
+        (false, false) => regions.dispatch_size = [0, 0],
+        // Both moved: an L shape, covered by the full image with the kept
+        // area skipped in the shader
+        (true, true) => {}
+    }
+
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view() -> RenderedView {
+        RenderedView {
+            center: [-0.5, 0.0],
+            zoom: 1.0,
+            stretch: 1.0,
+            max_iterations: 100,
+            debug_heatmap: false,
+            smooth_coloring: false,
+            palette: 0,
+            size: [800, 600],
+        }
+    }
+
+    // `prev` with the center moved by whole pixels
+    fn panned(prev: &RenderedView, pixels: [f32; 2]) -> RenderedView {
+        let pixel_size = prev.pixel_size();
+        RenderedView {
+            center: [
+                prev.center[0] + pixels[0] * pixel_size[0],
+                prev.center[1] + pixels[1] * pixel_size[1],
+            ],
+            ..*prev
+        }
+    }
+
+    #[test]
+    fn non_pan_changes_recompute_everything() {
+        let prev = view();
+        let changes = [
+            RenderedView { zoom: 2.0, ..prev },
+            RenderedView {
+                stretch: 2.0,
+                ..prev
+            },
+            RenderedView {
+                max_iterations: 200,
+                ..prev
+            },
+            RenderedView {
+                debug_heatmap: true,
+                ..prev
+            },
+            RenderedView {
+                smooth_coloring: true,
+                ..prev
+            },
+            RenderedView { palette: 1, ..prev },
+            RenderedView {
+                size: [801, 600],
+                ..prev
+            },
+        ];
+        for next in changes {
+            assert_eq!(plan_pan_reuse(&prev, &panned(&next, [3.0, 0.0])), None);
+        }
+    }
+
+    #[test]
+    fn a_screen_or_more_recomputes_everything() {
+        let prev = view();
+        assert_eq!(plan_pan_reuse(&prev, &panned(&prev, [800.0, 0.0])), None);
+        assert_eq!(plan_pan_reuse(&prev, &panned(&prev, [0.0, -600.0])), None);
+        assert_eq!(plan_pan_reuse(&prev, &panned(&prev, [2000.0, 10.0])), None);
+        assert!(plan_pan_reuse(&prev, &panned(&prev, [799.0, 599.0])).is_some());
+    }
+
+    #[test]
+    fn content_moves_against_the_center() {
+        let prev = view();
+        let shift = |pixels| plan_pan_reuse(&prev, &panned(&prev, pixels)).unwrap().shift;
+        assert_eq!(shift([5.0, 0.0]), [-5, 0]);
+        assert_eq!(shift([-5.0, 0.0]), [5, 0]);
+        assert_eq!(shift([0.0, 7.0]), [0, -7]);
+        assert_eq!(shift([0.0, -7.0]), [0, 7]);
+    }
+
+    #[test]
+    fn sub_pixel_remainder_is_left_for_the_next_pan() {
+        let prev = view();
+        let next = panned(&prev, [2.4, 0.0]);
+        let reuse = plan_pan_reuse(&prev, &next).unwrap();
+        assert_eq!(reuse.shift, [-2, 0]);
+        assert_eq!(reuse.center, panned(&prev, [2.0, 0.0]).center);
+    }
+
+    #[test]
+    fn single_axis_shifts_dispatch_one_strip() {
+        let size = [800, 600];
+        // Content moves right: the strip on the left is new
+        assert_eq!(
+            shift_regions(size, [10, 0]),
+            ShiftRegions {
+                copy_src: [0, 0],
+                copy_dst: [10, 0],
+                copy_size: [790, 600],
+                dispatch_origin: [0, 0],
+                dispatch_size: [10, 600],
+            }
+        );
+        // Content moves left: the strip on the right is new
+        assert_eq!(
+            shift_regions(size, [-10, 0]),
+            ShiftRegions {
+                copy_src: [10, 0],
+                copy_dst: [0, 0],
+                copy_size: [790, 600],
+                dispatch_origin: [790, 0],
+                dispatch_size: [10, 600],
+            }
+        );
+        // Content moves down: the strip at the top is new
+        assert_eq!(
+            shift_regions(size, [0, 20]),
+            ShiftRegions {
+                copy_src: [0, 0],
+                copy_dst: [0, 20],
+                copy_size: [800, 580],
+                dispatch_origin: [0, 0],
+                dispatch_size: [800, 20],
+            }
+        );
+        // Content moves up: the strip at the bottom is new
+        assert_eq!(
+            shift_regions(size, [0, -20]),
+            ShiftRegions {
+                copy_src: [0, 20],
+                copy_dst: [0, 0],
+                copy_size: [800, 580],
+                dispatch_origin: [0, 580],
+                dispatch_size: [800, 20],
+            }
+        );
+    }
+
+    #[test]
+    fn diagonal_shifts_dispatch_the_whole_image() {
+        // The L shape is covered by a full dispatch; the shader skips the
+        // kept area
+        assert_eq!(
+            shift_regions([800, 600], [-10, 20]),
+            ShiftRegions {
+                copy_src: [10, 0],
+                copy_dst: [0, 20],
+                copy_size: [790, 580],
+                dispatch_origin: [0, 0],
+                dispatch_size: [800, 600],
+            }
+        );
+    }
+
+    #[test]
+    fn no_shift_dispatches_nothing() {
+        assert_eq!(
+            shift_regions([800, 600], [0, 0]),
+            ShiftRegions {
+                copy_src: [0, 0],
+                copy_dst: [0, 0],
+                copy_size: [800, 600],
+                dispatch_origin: [0, 0],
+                dispatch_size: [0, 0],
+            }
+        );
+    }
+}
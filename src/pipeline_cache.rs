@@ -0,0 +1,95 @@
+use std::path::PathBuf;
+
+/// Compiled pipelines kept on disk between runs, so later starts skip
+/// most shader compilation. Only some backends (Vulkan) support it; on
+/// the rest there is simply no cache.
+pub struct PipelineCache {
+    cache: wgpu::PipelineCache,
+    path: PathBuf,
+    // Size of the data it was started from; none on a cold start
+    loaded: Option<usize>,
+}
+
+impl PipelineCache {
+    /// Opens the cache for `adapter`, seeded from its file if one exists.
+    /// The file lives in the per-user config directory, next to the
+    /// bookmarks. Its name is keyed to the adapter, and wgpu checks the
+    /// data's header against the driver, so a new GPU or driver starts
+    /// afresh.
+    pub fn load(device: &wgpu::Device, adapter: &wgpu::Adapter) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::PIPELINE_CACHE) {
+            return None;
+        }
+        let path = cache_dir()?.join(wgpu::util::pipeline_cache_key(&adapter.get_info())?);
+        let data = std::fs::read(&path).ok();
+
+        // SAFETY: the data is either absent or something this app wrote
+        // from an earlier cache; fallback discards it if it doesn't match
+        let cache = unsafe {
+            device.create_pipeline_cache(&wgpu::PipelineCacheDescriptor {
+                label: Some("Pipeline Cache"),
+                data: data.as_deref(),
+                fallback: true,
+            })
+        };
+
+        Some(Self {
+            cache,
+            path,
+            loaded: data.map(|data| data.len()),
+        })
+    }
+
+    pub fn cache(&self) -> &wgpu::PipelineCache {
+        &self.cache
+    }
+
+    /// One line for the diagnostics report.
+    pub fn describe(&self) -> String {
+        match self.loaded {
+            Some(bytes) => format!(
+                "Pipeline cache: {} (warm start, {} KiB)\n",
+                self.path.display(),
+                bytes / 1024
+            ),
+            None => format!("Pipeline cache: {} (cold start)\n", self.path.display()),
+        }
+    }
+
+    /// Writes the cache back to its file. It goes to a temporary file
+    /// first so a crash mid-write can't leave a truncated cache behind.
+    pub fn save(&self) -> std::io::Result<()> {
+        let Some(data) = self.cache.get_data() else {
+            return Ok(());
+        };
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let temp = self.path.with_extension("tmp");
+        std::fs::write(&temp, data)?;
+        std::fs::rename(&temp, &self.path)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn cache_dir() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("wgpu_paint"))
+}
+
+// Browsers have no pipeline caches to keep
+#[cfg(target_arch = "wasm32")]
+fn cache_dir() -> Option<PathBuf> {
+    None
+}
+
+impl Drop for PipelineCache {
+    fn drop(&mut self) {
+        if let Err(e) = self.save() {
+            log::error!(
+                "Unable to save the pipeline cache to {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::time::Duration;
+
+use web_time::Instant;
+
+// How much present history to keep around for reports
+const HISTORY_WINDOW: Duration = Duration::from_secs(5);
+// A frame counts as a missed vsync if its interval exceeds this many monitor periods
+const MISSED_FACTOR: f64 = 1.5;
+// Gaps longer than this are the app going idle, not a missed vsync
+const IDLE_GAP: Duration = Duration::from_millis(250);
+
+/// Inter-present timing and surface error counts, used to diagnose stutter.
+#[derive(Default)]
+pub struct PresentStats {
+    // (time of present, interval since the previous present)
+    history: VecDeque<(Instant, Duration)>,
+    last_present: Option<Instant>,
+    monitor_period: Option<Duration>,
+    pub presents: u64,
+    pub missed: u64,
+    pub suboptimal: u64,
+    pub outdated: u64,
+    pub lost: u64,
+    pub timeout: u64,
+    pub other_errors: u64,
+}
+
+impl PresentStats {
+    pub fn new(refresh_rate_millihertz: Option<u32>) -> Self {
+        let mut stats = Self::default();
+        stats.set_refresh_rate(refresh_rate_millihertz);
+        stats
+    }
+
+    /// Updates the monitor period, e.g. after the window moved to another monitor.
+    pub fn set_refresh_rate(&mut self, refresh_rate_millihertz: Option<u32>) {
+        self.monitor_period = refresh_rate_millihertz
+            .filter(|&mhz| mhz > 0)
+            .map(|mhz| Duration::from_secs_f64(1000.0 / mhz as f64));
+    }
+
+    /// Call just before `output.present()`.
+    pub fn record_present(&mut self, now: Instant) {
+        if let Some(last) = self.last_present {
+            let interval = now.duration_since(last);
+            if self.is_missed(interval) {
+                self.missed += 1;
+            }
+            self.history.push_back((now, interval));
+        }
+        self.last_present = Some(now);
+        self.presents += 1;
+
+        while let Some(&(time, _)) = self.history.front() {
+            if now.duration_since(time) > HISTORY_WINDOW {
+                self.history.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    pub fn record_suboptimal(&mut self) {
+        self.suboptimal += 1;
+    }
+
+    pub fn record_error(&mut self, error: &wgpu::SurfaceError) {
+        match error {
+            wgpu::SurfaceError::Outdated => self.outdated += 1,
+            wgpu::SurfaceError::Lost => self.lost += 1,
+            wgpu::SurfaceError::Timeout => self.timeout += 1,
+            _ => self.other_errors += 1,
+        }
+    }
+
+    /// Recent present intervals, oldest first, each flagged if it missed vsync.
+    pub fn recent_intervals(&self) -> impl DoubleEndedIterator<Item = (Duration, bool)> + '_ {
+        self.history
+            .iter()
+            .map(|&(_, interval)| (interval, self.is_missed(interval)))
+    }
+
+    /// Average interval over the last second of presents, ignoring idle gaps.
+    pub fn average_interval(&self) -> Option<Duration> {
+        let now = self.last_present?;
+        let recent: Vec<Duration> = self
+            .history
+            .iter()
+            .rev()
+            .take_while(|(time, _)| now.duration_since(*time) <= Duration::from_secs(1))
+            .map(|&(_, interval)| interval)
+            .filter(|&interval| interval < IDLE_GAP)
+            .collect();
+        if recent.is_empty() {
+            return None;
+        }
+        Some(recent.iter().sum::<Duration>() / recent.len() as u32)
+    }
+
+    pub fn monitor_period(&self) -> Option<Duration> {
+        self.monitor_period
+    }
+
+    fn is_missed(&self, interval: Duration) -> bool {
+        match self.monitor_period {
+            Some(period) => {
+                interval < IDLE_GAP && interval.as_secs_f64() > period.as_secs_f64() * MISSED_FACTOR
+            }
+            None => false,
+        }
+    }
+
+    /// Writes a plain-text report suitable for pasting into a bug report.
+    pub fn report(
+        &self,
+        adapter_info: &wgpu::AdapterInfo,
+        limits: &wgpu::Limits,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> String {
+        let mut out = String::new();
+
+        let _ = writeln!(
+            out,
+            "Adapter: {} ({:?})",
+            adapter_info.name, adapter_info.device_type
+        );
+        let _ = writeln!(out, "Backend: {:?}", adapter_info.backend);
+        let _ = writeln!(
+            out,
+            "Driver: {} {}",
+            adapter_info.driver, adapter_info.driver_info
+        );
+        let _ = writeln!(out, "Surface format: {:?}", config.format);
+        let _ = writeln!(out, "Present mode: {:?}", config.present_mode);
+        let _ = writeln!(out, "Surface size: {}x{}", config.width, config.height);
+        match self.monitor_period {
+            Some(period) => {
+                let _ = writeln!(
+                    out,
+                    "Monitor period: {:.2} ms",
+                    period.as_secs_f64() * 1000.0
+                );
+            }
+            None => {
+                let _ = writeln!(out, "Monitor period: unknown");
+            }
+        }
+
+        let _ = writeln!(out, "Presents: {}", self.presents);
+        let _ = writeln!(out, "Missed vsync: {}", self.missed);
+        let _ = writeln!(out, "Suboptimal: {}", self.suboptimal);
+        let _ = writeln!(
+            out,
+            "Errors: outdated {}, lost {}, timeout {}, other {}",
+            self.outdated, self.lost, self.timeout, self.other_errors
+        );
+
+        let _ = writeln!(
+            out,
+            "Last {}s of present intervals (ms):",
+            HISTORY_WINDOW.as_secs()
+        );
+        for (_, interval) in &self.history {
+            let ms = interval.as_secs_f64() * 1000.0;
+            let flag = if self.is_missed(*interval) { " *" } else { "" };
+            let _ = writeln!(out, "  {:.2}{}", ms, flag);
+        }
+
+        let _ = writeln!(out, "Limits: {:#?}", limits);
+
+        out
+    }
+}
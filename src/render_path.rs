@@ -0,0 +1,602 @@
+/// How the fractal gets into the canvas textures, picked once from what
+/// the adapter supports.
+pub enum RenderPath {
+    /// Compute shaders writing storage textures. The brush is a compute
+    /// shader too, so painting is only available on this path.
+    Compute(ComputePath),
+    /// A fragment shader drawing into the canvas as a render target, for
+    /// backends without compute shaders or storage textures (WebGL2).
+    Fragment(FragmentPath),
+}
+
+/// The buffers and views the fractal and brush passes bind. The views
+/// change on resize.
+pub struct CanvasBindings<'a> {
+    pub canvas_view_a: &'a wgpu::TextureView,
+    pub canvas_view_b: &'a wgpu::TextureView,
+    pub preview_view: &'a wgpu::TextureView,
+    pub params_buffer: &'a wgpu::Buffer,
+    pub palette_buffer: &'a wgpu::Buffer,
+    pub brush_params_buffer: &'a wgpu::Buffer,
+}
+
+impl RenderPath {
+    /// Whether `adapter` can run the compute path.
+    pub fn supports_compute(adapter: &wgpu::Adapter) -> bool {
+        adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+            && adapter.limits().max_storage_textures_per_shader_stage > 0
+    }
+
+    /// Usages the fractal passes need on the textures they write.
+    pub fn target_usages(compute: bool) -> wgpu::TextureUsages {
+        if compute {
+            wgpu::TextureUsages::STORAGE_BINDING
+        } else {
+            wgpu::TextureUsages::RENDER_ATTACHMENT
+        }
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        compute: bool,
+        bindings: &CanvasBindings,
+        cache: Option<&wgpu::PipelineCache>,
+    ) -> Self {
+        if compute {
+            Self::Compute(ComputePath::new(device, bindings, cache))
+        } else {
+            Self::Fragment(FragmentPath::new(device, bindings, cache))
+        }
+    }
+
+    /// Points the passes at new canvas and preview views.
+    pub fn rebind(&mut self, device: &wgpu::Device, bindings: &CanvasBindings) {
+        match self {
+            Self::Compute(compute) => {
+                compute.bind_groups = ComputeBindGroups::new(
+                    device,
+                    &compute.bind_group_layout,
+                    &compute.brush_bind_group_layout,
+                    bindings,
+                )
+            }
+            // Only the render target changes, and that isn't bound
+            Self::Fragment(_) => {}
+        }
+    }
+
+    pub fn is_compute(&self) -> bool {
+        matches!(self, Self::Compute(_))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Compute(_) => "compute",
+            Self::Fragment(_) => "fragment",
+        }
+    }
+}
+
+pub struct ComputePath {
+    pub pipeline: wgpu::ComputePipeline,
+    pub brush_pipeline: wgpu::ComputePipeline,
+    // Spelled out rather than reflected from the shaders, so moving a
+    // binding in the WGSL fails pipeline creation instead of quietly
+    // changing what the bind groups below have to match
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub brush_bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_groups: ComputeBindGroups,
+}
+
+pub struct ComputeBindGroups {
+    // Each pass writes the texture the previous one didn't
+    pub a_to_b: wgpu::BindGroup,
+    pub b_to_a: wgpu::BindGroup,
+    pub preview: wgpu::BindGroup,
+    // The brush paints into whichever texture holds the latest frame,
+    // blending over a copy of it in the other one
+    pub brush_a: wgpu::BindGroup,
+    pub brush_b: wgpu::BindGroup,
+}
+
+impl ComputePath {
+    fn new(
+        device: &wgpu::Device,
+        bindings: &CanvasBindings,
+        cache: Option<&wgpu::PipelineCache>,
+    ) -> Self {
+        let compute_shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Compute Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(
+                    include_str!("mandelbrot.wgsl"),
+                    include_str!("compute.wgsl")
+                )
+                .into(),
+            ),
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Mandelbrot Compute Bind Group Layout"),
+            entries: &[
+                storage_texture_entry(0),
+                uniform_entry(1, wgpu::ShaderStages::COMPUTE),
+                uniform_entry(2, wgpu::ShaderStages::COMPUTE),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Mandelbrot Compute Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &compute_shader_module,
+            entry_point: Some("compute"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache,
+        });
+
+        let brush_shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Brush Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("brush.wgsl").into()),
+        });
+        let brush_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Brush Bind Group Layout"),
+                entries: &[
+                    storage_texture_entry(0),
+                    uniform_entry(1, wgpu::ShaderStages::COMPUTE),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let brush_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Brush Pipeline Layout"),
+                bind_group_layouts: &[&brush_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let brush_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Brush Pipeline"),
+            layout: Some(&brush_pipeline_layout),
+            module: &brush_shader_module,
+            entry_point: Some("splat"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache,
+        });
+
+        let bind_groups = ComputeBindGroups::new(
+            device,
+            &bind_group_layout,
+            &brush_bind_group_layout,
+            bindings,
+        );
+        Self {
+            pipeline,
+            brush_pipeline,
+            bind_group_layout,
+            brush_bind_group_layout,
+            bind_groups,
+        }
+    }
+
+    /// A bind group for the Mandelbrot pipeline writing `target`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn bind_target(
+        &self,
+        device: &wgpu::Device,
+        label: &str,
+        target: &wgpu::TextureView,
+        params_buffer: &wgpu::Buffer,
+        palette_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        mandelbrot_bind_group(
+            device,
+            &self.bind_group_layout,
+            label,
+            target,
+            params_buffer,
+            palette_buffer,
+        )
+    }
+}
+
+impl ComputeBindGroups {
+    fn new(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        brush_layout: &wgpu::BindGroupLayout,
+        bindings: &CanvasBindings,
+    ) -> Self {
+        let mandelbrot = |label: &str, target: &wgpu::TextureView| {
+            mandelbrot_bind_group(
+                device,
+                layout,
+                label,
+                target,
+                bindings.params_buffer,
+                bindings.palette_buffer,
+            )
+        };
+
+        let brush = |label: &str, canvas: &wgpu::TextureView, scratch: &wgpu::TextureView| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(label),
+                layout: brush_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(canvas),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: bindings.brush_params_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(scratch),
+                    },
+                ],
+            })
+        };
+
+        Self {
+            a_to_b: mandelbrot(
+                "Mandelbrot Compute Bind Group A to B",
+                bindings.canvas_view_b,
+            ),
+            b_to_a: mandelbrot(
+                "Mandelbrot Compute Bind Group B to A",
+                bindings.canvas_view_a,
+            ),
+            preview: mandelbrot("Preview Compute Bind Group", bindings.preview_view),
+            brush_a: brush(
+                "Brush Bind Group A",
+                bindings.canvas_view_a,
+                bindings.canvas_view_b,
+            ),
+            brush_b: brush(
+                "Brush Bind Group B",
+                bindings.canvas_view_b,
+                bindings.canvas_view_a,
+            ),
+        }
+    }
+}
+
+/// A write-only Rgba8Unorm storage texture, as every compute pass writes
+/// the canvas.
+fn storage_texture_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::StorageTexture {
+            access: wgpu::StorageTextureAccess::WriteOnly,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            view_dimension: wgpu::TextureViewDimension::D2,
+        },
+        count: None,
+    }
+}
+
+fn uniform_entry(binding: u32, visibility: wgpu::ShaderStages) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+fn mandelbrot_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    label: &str,
+    target: &wgpu::TextureView,
+    params_buffer: &wgpu::Buffer,
+    palette_buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(label),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(target),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: params_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: palette_buffer.as_entire_binding(),
+            },
+        ],
+    })
+}
+
+pub struct FragmentPath {
+    pub pipeline: wgpu::RenderPipeline,
+    // Spelled out like the compute layouts. Parameters and palette only;
+    // the target is the pass's color attachment
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl FragmentPath {
+    fn new(
+        device: &wgpu::Device,
+        bindings: &CanvasBindings,
+        cache: Option<&wgpu::PipelineCache>,
+    ) -> Self {
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Fractal Fragment Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(
+                    include_str!("mandelbrot.wgsl"),
+                    include_str!("fractal.wgsl")
+                )
+                .into(),
+            ),
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Fractal Fragment Bind Group Layout"),
+            entries: &[
+                uniform_entry(1, wgpu::ShaderStages::FRAGMENT),
+                uniform_entry(2, wgpu::ShaderStages::FRAGMENT),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Fractal Fragment Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Fractal Fragment Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: Some("fs_fractal"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: wgpu::TextureFormat::Rgba8Unorm,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache,
+        });
+        let bind_group = fragment_bind_group(
+            device,
+            &bind_group_layout,
+            bindings.params_buffer,
+            bindings.palette_buffer,
+        );
+        Self {
+            pipeline,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    /// A bind group for the fractal pipeline reading `params_buffer`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn bind_params(
+        &self,
+        device: &wgpu::Device,
+        params_buffer: &wgpu::Buffer,
+        palette_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        fragment_bind_group(
+            device,
+            &self.bind_group_layout,
+            params_buffer,
+            palette_buffer,
+        )
+    }
+
+    /// Encodes drawing the fractal into `rect` ([x, y, width, height] in
+    /// texels) of `target`, leaving the rest of it as it was.
+    pub fn encode(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::Texture,
+        bind_group: &wgpu::BindGroup,
+        rect: [u32; 4],
+        timestamp_writes: Option<wgpu::RenderPassTimestampWrites>,
+    ) {
+        let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Mandelbrot Fragment Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+                depth_slice: None,
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes,
+        });
+
+        // Clipped to the target, like the compute shader's bounds check
+        let [x, y, width, height] = rect;
+        let x = x.min(target.width());
+        let y = y.min(target.height());
+        let width = width.min(target.width() - x);
+        let height = height.min(target.height() - y);
+        if width == 0 || height == 0 {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.set_scissor_rect(x, y, width, height);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn fragment_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    params_buffer: &wgpu::Buffer,
+    palette_buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Fractal Fragment Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: params_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: palette_buffer.as_entire_binding(),
+            },
+        ],
+    })
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::palette::PaletteUniform;
+    use crate::test_gpu;
+
+    fn uniform_buffer(device: &wgpu::Device, size: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: size as u64,
+            usage: wgpu::BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        })
+    }
+
+    // Builds `compute`'s path with every bind group, and encodes a pass
+    // with each, all inside a validation error scope
+    fn build_and_bind(device: &wgpu::Device, queue: &wgpu::Queue, compute: bool) {
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+        let texture = || {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: wgpu::Extent3d {
+                    width: 16,
+                    height: 16,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | RenderPath::target_usages(compute),
+                view_formats: &[],
+            })
+        };
+        let textures = [texture(), texture(), texture()];
+        let views = textures
+            .each_ref()
+            .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()));
+        // Larger than any of the parameter structs
+        let params_buffer = uniform_buffer(device, 256);
+        let palette_buffer = uniform_buffer(device, std::mem::size_of::<PaletteUniform>());
+        let brush_params_buffer = uniform_buffer(device, 256);
+        let bindings = CanvasBindings {
+            canvas_view_a: &views[0],
+            canvas_view_b: &views[1],
+            preview_view: &views[2],
+            params_buffer: &params_buffer,
+            palette_buffer: &palette_buffer,
+            brush_params_buffer: &brush_params_buffer,
+        };
+        let mut render_path = RenderPath::new(device, compute, &bindings, None);
+        // Resizing rebinds against the stored layouts
+        render_path.rebind(device, &bindings);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        match &render_path {
+            RenderPath::Compute(path) => {
+                let groups = &path.bind_groups;
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+                pass.set_pipeline(&path.pipeline);
+                for group in [&groups.a_to_b, &groups.b_to_a, &groups.preview] {
+                    pass.set_bind_group(0, group, &[]);
+                    pass.dispatch_workgroups(1, 1, 1);
+                }
+                pass.set_pipeline(&path.brush_pipeline);
+                for group in [&groups.brush_a, &groups.brush_b] {
+                    pass.set_bind_group(0, group, &[]);
+                    pass.dispatch_workgroups(1, 1, 1);
+                }
+            }
+            RenderPath::Fragment(path) => {
+                path.encode(
+                    &mut encoder,
+                    &textures[0],
+                    &path.bind_group,
+                    [0, 0, 16, 16],
+                    None,
+                );
+            }
+        }
+        queue.submit([encoder.finish()]);
+
+        let error = pollster::block_on(device.pop_error_scope());
+        assert!(error.is_none(), "{}", error.unwrap());
+    }
+
+    #[test]
+    #[cfg_attr(
+        not(feature = "gpu-tests"),
+        ignore = "needs a GPU, see --features gpu-tests"
+    )]
+    fn compute_pipelines_accept_their_bind_groups() {
+        let adapter = test_gpu::adapter();
+        assert!(
+            RenderPath::supports_compute(&adapter),
+            "the test adapter has no compute path to check"
+        );
+        let (device, queue) = test_gpu::open(&adapter);
+        build_and_bind(&device, &queue, true);
+    }
+
+    #[test]
+    #[cfg_attr(
+        not(feature = "gpu-tests"),
+        ignore = "needs a GPU, see --features gpu-tests"
+    )]
+    fn fragment_pipeline_accepts_its_bind_group() {
+        let (device, queue) = test_gpu::device();
+        build_and_bind(&device, &queue, false);
+    }
+}
@@ -0,0 +1,161 @@
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use web_time::Instant;
+
+const HEADER: &str = "elapsed_ms,center_re,center_im,zoom,max_iterations,kind,gpu_compute_ms";
+
+/// One fractal dispatch, as a CSV row.
+struct Row {
+    // Frame number, to match GPU timings that arrive later
+    frame: u64,
+    elapsed: Duration,
+    center: [f32; 2],
+    zoom: f32,
+    max_iterations: u32,
+    kind: &'static str,
+    gpu_compute: Option<Duration>,
+}
+
+/// Opt-in record of every fractal render in a session, written as CSV
+/// when recording stops or the app exits. Columns are in [`HEADER`];
+/// `kind` is `full`, `pan` or `preview`, and `gpu_compute_ms` is empty
+/// when timestamp queries are unavailable.
+pub struct SessionStats {
+    path: PathBuf,
+    started: Instant,
+    rows: Vec<Row>,
+}
+
+impl SessionStats {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            started: Instant::now(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Notes a dispatch made in `frame`.
+    pub fn record(
+        &mut self,
+        frame: u64,
+        center: [f32; 2],
+        zoom: f32,
+        max_iterations: u32,
+        kind: &'static str,
+    ) {
+        self.rows.push(Row {
+            frame,
+            elapsed: self.started.elapsed(),
+            center,
+            zoom,
+            max_iterations,
+            kind,
+            gpu_compute: None,
+        });
+    }
+
+    /// Fills in the GPU time of the dispatch made in `frame`, once its
+    /// timestamps have been read back.
+    pub fn attach_timing(&mut self, frame: u64, compute: Duration) {
+        if let Some(row) = self.rows.iter_mut().rev().find(|row| row.frame == frame) {
+            row.gpu_compute = Some(compute);
+        }
+    }
+
+    /// Appends the recorded rows to the file, with the header if the file
+    /// is new, and forgets them.
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        let new_file = !self.path.exists();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let mut out = BufWriter::new(file);
+        if new_file {
+            writeln!(out, "{}", HEADER)?;
+        }
+        for row in self.rows.drain(..) {
+            let gpu_compute = row
+                .gpu_compute
+                .map(|compute| format!("{:.3}", compute.as_secs_f64() * 1000.0))
+                .unwrap_or_default();
+            writeln!(
+                out,
+                "{:.1},{},{},{},{},{},{}",
+                row.elapsed.as_secs_f64() * 1000.0,
+                row.center[0],
+                row.center[1],
+                row.zoom,
+                row.max_iterations,
+                row.kind,
+                gpu_compute
+            )?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for SessionStats {
+    fn drop(&mut self) {
+        match self.flush() {
+            Ok(()) => log::info!("Session stats saved to {}", self.path.display()),
+            Err(e) => log::error!(
+                "Unable to save session stats to {}: {}",
+                self.path.display(),
+                e
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flush_writes_a_header_then_appends() {
+        let path = std::env::temp_dir().join(format!(
+            "wgpu_paint_session_stats_{}.csv",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let mut stats = SessionStats::new(path.clone());
+        stats.record(1, [-0.5, 0.0], 1.0, 100, "preview");
+        stats.record(2, [-0.5, 0.0], 1.0, 100, "full");
+        stats.attach_timing(2, Duration::from_micros(1500));
+        stats.flush().unwrap();
+        stats.record(3, [-0.25, 0.125], 2.0, 150, "pan");
+        // Dropping flushes the rest
+        drop(stats);
+
+        let csv = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], HEADER);
+        let columns = HEADER.split(',').count();
+        let rows: Vec<Vec<_>> = lines[1..]
+            .iter()
+            .map(|line| line.split(',').collect())
+            .collect();
+        for row in &rows {
+            assert_eq!(row.len(), columns);
+        }
+        assert_eq!(rows[0][1..], ["-0.5", "0", "1", "100", "preview", ""]);
+        assert_eq!(rows[1][1..], ["-0.5", "0", "1", "100", "full", "1.500"]);
+        assert_eq!(rows[2][1..], ["-0.25", "0.125", "2", "150", "pan", ""]);
+    }
+}
@@ -1,23 +1,19 @@
-struct Vertex {
-    @location(0) position: vec3<f32>,
-    @location(1) color: vec3<f32>,
-}
-
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) uv: vec2<f32>,
 }
 
+// Fullscreen triangle generated from the vertex index, so no vertex buffer
+// is needed: (-1, -1), (3, -1), (-1, 3) in clip space covers the screen.
 @vertex
-fn vs_main(vertex: Vertex) -> VertexOutput {
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
     var out: VertexOutput;
 
-    out.clip_position = vec4<f32>(vertex.position, 1.0);
+    let corner = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    out.clip_position = vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
 
-    // Convert from clip space [-1, 1] to UV space [0, 1]
-    out.uv = (vertex.position.xy + 1.0) * 0.5;
-    // Flip Y coordinate for texture sampling
-    out.uv.y = 1.0 - out.uv.y;
+    // Clip space [-1, 1] to UV space [0, 1], flipping Y for texture sampling
+    out.uv = vec2<f32>(corner.x, 1.0 - corner.y);
 
     return out;
 }
@@ -28,7 +24,22 @@ var canvas_texture: texture_2d<f32>;
 @group(0) @binding(1)
 var canvas_sampler: sampler;
 
+// Set per pipeline: whether the surface sRGB-encodes what's written to
+// it. The canvas already holds display values, so they're decoded first
+// and the surface's encoding gives them back unchanged.
+override SRGB_SURFACE: bool = false;
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(canvas_texture, canvas_sampler, in.uv);
+    let color = textureSample(canvas_texture, canvas_sampler, in.uv);
+    if SRGB_SURFACE {
+        return vec4<f32>(srgb_to_linear(color.rgb), color.a);
+    }
+    return color;
 }
@@ -1,21 +1,64 @@
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
 
 use wgpu::{
     util::{BufferInitDescriptor, DeviceExt},
     *,
 };
-use winit::{event_loop::ActiveEventLoop, keyboard::KeyCode, window::Window};
+use winit::{
+    event_loop::ActiveEventLoop,
+    keyboard::{KeyCode, ModifiersState},
+    window::Window,
+};
 
-use crate::shader;
+use crate::{
+    bookmarks::{Bookmark, Bookmarks},
+    command::Command,
+    gpu_timer::{GpuTimer, GpuTimings},
+    history::{CanvasHistory, Restored},
+    hud::{self, Hud, HudColors},
+    minibrot,
+    palette::PALETTES,
+    pan_reuse::{self, RenderedView},
+    pipeline_cache::PipelineCache,
+    present_stats::PresentStats,
+    render_path::{CanvasBindings, RenderPath},
+    submit_log::{self, SubmitLog},
+    view_history::{ViewEntry, ViewHistory},
+};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{export, session_stats::SessionStats};
 
 pub struct MandelbrotState {
-    pub center: [f32; 2],
-    pub zoom: f32,
-    pub max_iterations: u32,
-    pub cursor_location: [f64; 2],
-    pub prev_cursor_location: [f64; 2],
-    pub dragging: bool,
-    pub needs_update: bool,
+    pub(crate) center: [f32; 2],
+    pub(crate) zoom: f32,
+    // Extra vertical magnification on top of zoom (1.0 = no anamorphic stretch)
+    pub(crate) stretch: f32,
+    pub(crate) max_iterations: u32,
+    pub(crate) cursor_location: [f64; 2],
+    pub(crate) prev_cursor_location: [f64; 2],
+    // Whether cursor_location is a real position yet
+    pub(crate) cursor_in_window: bool,
+    pub(crate) dragging: bool,
+    // Drag motion (normalized screen units) not yet applied to center
+    pub(crate) pan_delta: [f64; 2],
+    pub(crate) needs_update: bool,
+    pub(crate) debug_heatmap: bool,
+    // Continuous escape-time coloring instead of per-iteration bands
+    pub(crate) smooth_coloring: bool,
+    // Index into palette::PALETTES
+    pub(crate) palette: usize,
+    // Derive max_iterations from the zoom instead of setting it by hand
+    pub(crate) auto_iterations: bool,
+    // Manual adjustment on top of the automatic value
+    pub(crate) auto_iterations_offset: i32,
+    // Upper limit for the automatic value
+    pub(crate) auto_iterations_ceiling: u32,
 }
 
 impl Default for MandelbrotState {
@@ -23,13 +66,160 @@ impl Default for MandelbrotState {
         Self {
             center: [-0.5, 0.0], // Default center of Mandelbrot set
             zoom: 1.0,
+            stretch: 1.0,
             max_iterations: 100,
             cursor_location: [0.0, 0.0],
             prev_cursor_location: [0.0, 0.0],
+            cursor_in_window: false,
             dragging: false,
+            pan_delta: [0.0, 0.0],
             needs_update: true,
+            debug_heatmap: false,
+            smooth_coloring: false,
+            palette: 0,
+            auto_iterations: false,
+            auto_iterations_offset: 0,
+            auto_iterations_ceiling: 10_000,
+        }
+    }
+}
+
+impl MandelbrotState {
+    /// Maps a normalized screen position ([0, 1] on both axes) to the point
+    /// of the complex plane shown there. Must match the mapping in compute.wgsl.
+    pub(crate) fn screen_to_complex(&self, screen: [f64; 2], aspect_ratio: f32) -> [f32; 2] {
+        let offset = self.screen_delta_to_complex([screen[0] - 0.5, screen[1] - 0.5], aspect_ratio);
+        [self.center[0] + offset[0], self.center[1] + offset[1]]
+    }
+
+    /// Converts a distance in normalized screen units to the complex plane.
+    pub(crate) fn screen_delta_to_complex(&self, delta: [f64; 2], aspect_ratio: f32) -> [f32; 2] {
+        [
+            delta[0] as f32 * 2.0 * aspect_ratio / self.zoom,
+            delta[1] as f32 * 2.0 / (self.zoom * self.stretch),
+        ]
+    }
+
+    /// Applies and clears the accumulated drag so the grabbed point follows the cursor.
+    pub(crate) fn apply_pan(&mut self, aspect_ratio: f32) {
+        let delta = std::mem::take(&mut self.pan_delta);
+        if delta == [0.0, 0.0] {
+            return;
+        }
+        let offset = self.screen_delta_to_complex(delta, aspect_ratio);
+        self.center[0] -= offset[0];
+        self.center[1] -= offset[1];
+        self.needs_update = true;
+    }
+
+    /// Sets the zoom, unless it's zero, negative or not finite, which
+    /// the shaders can't draw. Returns whether it was taken.
+    pub(crate) fn set_zoom(&mut self, zoom: f32) -> bool {
+        if !usable_scale(zoom) {
+            log::warn!("Ignoring zoom of {}", zoom);
+            return false;
         }
+        self.zoom = zoom;
+        true
     }
+
+    /// Multiplies the zoom; see [`MandelbrotState::set_zoom`].
+    pub(crate) fn zoom_by(&mut self, factor: f32) -> bool {
+        self.set_zoom(self.zoom * factor)
+    }
+
+    /// Multiplies the vertical stretch, unless that leaves it zero,
+    /// negative or not finite. Returns whether it changed.
+    pub(crate) fn stretch_by(&mut self, factor: f32) -> bool {
+        let stretch = self.stretch * factor;
+        if !usable_scale(stretch) {
+            log::warn!("Ignoring stretch of {}", stretch);
+            return false;
+        }
+        self.stretch = stretch;
+        true
+    }
+
+    /// Multiplies the zoom while keeping the point under `screen` in place.
+    pub(crate) fn zoom_at(&mut self, factor: f32, screen: [f64; 2], aspect_ratio: f32) {
+        if !usable_scale(self.zoom * factor) {
+            log::warn!("Ignoring zoom of {}", self.zoom * factor);
+            return;
+        }
+        // Pending drag motion was made at the old zoom
+        self.apply_pan(aspect_ratio);
+        let before = self.screen_to_complex(screen, aspect_ratio);
+        self.zoom *= factor;
+        let after = self.screen_to_complex(screen, aspect_ratio);
+        self.center[0] += before[0] - after[0];
+        self.center[1] += before[1] - after[1];
+    }
+
+    /// Takes on the view a canvas was rendered in, so that canvas is shown
+    /// as it is instead of being recomputed. The palette also has to be
+    /// uploaded; see `State::set_palette`.
+    pub(crate) fn show_view(&mut self, view: &RenderedView) {
+        self.pan_delta = [0.0, 0.0];
+        self.center = view.center;
+        self.zoom = view.zoom;
+        self.stretch = view.stretch;
+        self.max_iterations = view.max_iterations;
+        self.debug_heatmap = view.debug_heatmap;
+        self.smooth_coloring = view.smooth_coloring;
+        self.palette = view.palette;
+        self.needs_update = false;
+    }
+
+    /// Zooms about the cursor, or about the center if we don't know where
+    /// the cursor is.
+    pub(crate) fn zoom_at_cursor(&mut self, factor: f32, aspect_ratio: f32) {
+        let anchor = if self.cursor_in_window {
+            self.cursor_location
+        } else {
+            [0.5, 0.5]
+        };
+        self.zoom_at(factor, anchor, aspect_ratio);
+    }
+}
+
+/// Whether a zoom or stretch can be drawn: finite and above zero.
+fn usable_scale(value: f32) -> bool {
+    value.is_finite() && value > 0.0
+}
+
+/// Adds a motion of `delta` (normalized screen units) to a pan
+/// accumulator. Every event contributes exactly its own motion, however
+/// small, so N small moves pan as far as one big one no matter how they
+/// fall between frames.
+pub(crate) fn accumulate_pan(pan_delta: [f64; 2], delta: [f64; 2]) -> [f64; 2] {
+    [pan_delta[0] + delta[0], pan_delta[1] + delta[1]]
+}
+
+// Shift+arrow pan step as a fraction of the window, so it shrinks in the
+// complex plane as you zoom in
+const KEY_PAN_STEP: f64 = 0.1;
+
+// Fewest iterations the arrow keys or auto mode go down to
+const MIN_ITERATIONS: u32 = 10;
+// Arrow key step, in iterations
+const ITERATIONS_STEP: i32 = 50;
+// Auto mode: iterations at zoom 1, and how many more per doubling of zoom
+const AUTO_ITERATIONS_BASE: f32 = 100.0;
+const AUTO_ITERATIONS_PER_OCTAVE: f32 = 50.0;
+// Zoom for a minibrot of size 1 (the whole set) after snapping; smaller
+// ones get proportionally more
+const MINIBROT_FRAMING: f64 = 0.8;
+
+/// Iteration limit for auto mode: grows with log2 of the zoom, since deeper
+/// views need more iterations to resolve the boundary. Never goes below
+/// `MIN_ITERATIONS` or above `ceiling`.
+pub(crate) fn auto_iterations(zoom: f32, offset: i32, ceiling: u32) -> u32 {
+    let octaves = zoom.max(1.0).log2();
+    let iterations = AUTO_ITERATIONS_BASE + AUTO_ITERATIONS_PER_OCTAVE * octaves + offset as f32;
+    let ceiling = ceiling.max(MIN_ITERATIONS);
+    iterations
+        .round()
+        .clamp(MIN_ITERATIONS as f32, ceiling as f32) as u32
 }
 
 #[repr(C)]
@@ -38,68 +228,638 @@ struct MandelbrotParams {
     center: [f32; 2],
     zoom: f32,
     max_iterations: u32,
-    _padding: [f32; 2], // Align to 16 bytes
+    debug_heatmap: u32,
+    stretch: f32,
+    // Top-left of the dispatched region
+    origin: [u32; 2],
+    // Pixels inside [reuse_min, reuse_max) were kept from the last frame
+    reuse_min: [u32; 2],
+    reuse_max: [u32; 2],
+    smooth_coloring: u32,
+    _padding0: u32,
+    // Placement of the output texture in the whole image (tiled exports)
+    tile_offset: [u32; 2],
+    image_size: [u32; 2],
+    // Keeps the struct a multiple of 16 bytes
+    _padding1: [u32; 2],
 }
 
-// Fullscreen quad vertices
-const QUAD_VERTICES: &[shader::Vertex] = &[
-    shader::Vertex {
-        position: [-1.0, -1.0, 0.0],
-        color: [0.0, 0.0, 0.0], // Will be replaced by texture
-    },
-    shader::Vertex {
-        position: [1.0, -1.0, 0.0],
-        color: [1.0, 0.0, 0.0],
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BrushParams {
+    color: [f32; 4],
+    p0: [f32; 2],
+    p1: [f32; 2],
+    origin: [u32; 2],
+    radius: f32,
+    hardness: f32,
+    erase: u32,
+    _padding: [u32; 3],
+}
+
+// Ctrl+E exports at this multiple of the window size
+const EXPORT_SCALE: u32 = 4;
+// Largest export tile; also capped by the device's texture size limit
+#[cfg(not(target_arch = "wasm32"))]
+const EXPORT_TILE_SIZE: u32 = 2048;
+// Rows computed and encoded at a time; bounds export memory use
+#[cfg(not(target_arch = "wasm32"))]
+const EXPORT_STRIP_HEIGHT: u32 = 256;
+
+// Brush radius range in canvas texels
+const MIN_BRUSH_RADIUS: f32 = 1.0;
+const MAX_BRUSH_RADIUS: f32 = 500.0;
+// Per key press: radius is scaled, hardness is stepped
+const BRUSH_RADIUS_STEP: f32 = 1.2;
+const BRUSH_HARDNESS_STEP: f32 = 0.1;
+// Logical pixels the cursor may wobble during a press before it's a drag
+const DRAG_THRESHOLD: f64 = 4.0;
+// Holding the button this long makes any motion a drag
+const DRAG_HOLD_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(300);
+
+/// Where a left-button press is in becoming a click or a drag.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Gesture {
+    Idle,
+    /// Pressed but not yet moved past the drag threshold.
+    Pending {
+        /// Normalized screen position of the press.
+        start: [f64; 2],
+        pressed_at: web_time::Instant,
     },
-    shader::Vertex {
-        position: [1.0, 1.0, 0.0],
-        color: [1.0, 1.0, 0.0],
+    Dragging,
+}
+
+/// Pointer input the gesture state machine reacts to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum PointerEvent {
+    /// Left button down at a normalized screen position.
+    Press {
+        at: [f64; 2],
+        now: web_time::Instant,
     },
-    shader::Vertex {
-        position: [-1.0, -1.0, 0.0],
-        color: [0.0, 0.0, 0.0],
+    /// The cursor moved to a normalized screen position.
+    Move {
+        to: [f64; 2],
+        now: web_time::Instant,
     },
-    shader::Vertex {
-        position: [1.0, 1.0, 0.0],
-        color: [1.0, 1.0, 0.0],
+    /// Left button up.
+    Release,
+}
+
+/// What a gesture transition asks the current tool to do.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum GestureAction {
+    None,
+    /// Start a drag from the press position, catching up on the motion
+    /// since.
+    StartDrag {
+        start: [f64; 2],
     },
-    shader::Vertex {
-        position: [-1.0, 1.0, 0.0],
-        color: [0.0, 1.0, 0.0],
+    /// Carry the drag on to the cursor.
+    ContinueDrag,
+    EndDrag,
+    /// The press resolved as a click where it went down.
+    Click {
+        at: [f64; 2],
     },
-];
+}
+
+/// The click/drag state machine. `window_size` in physical pixels turns
+/// normalized cursor motion into the distance compared against
+/// `threshold`.
+fn gesture_transition(
+    gesture: Gesture,
+    event: PointerEvent,
+    window_size: [f64; 2],
+    threshold: f64,
+) -> (Gesture, GestureAction) {
+    match (gesture, event) {
+        (Gesture::Idle, PointerEvent::Move { .. } | PointerEvent::Release) => {
+            (Gesture::Idle, GestureAction::None)
+        }
+        (Gesture::Idle | Gesture::Pending { .. }, PointerEvent::Press { at, now }) => (
+            Gesture::Pending {
+                start: at,
+                pressed_at: now,
+            },
+            GestureAction::None,
+        ),
+        (Gesture::Pending { start, pressed_at }, PointerEvent::Move { to, now }) => {
+            let distance =
+                ((to[0] - start[0]) * window_size[0]).hypot((to[1] - start[1]) * window_size[1]);
+            if distance > threshold || now.duration_since(pressed_at) >= DRAG_HOLD_TIMEOUT {
+                (Gesture::Dragging, GestureAction::StartDrag { start })
+            } else {
+                (gesture, GestureAction::None)
+            }
+        }
+        (Gesture::Pending { start, .. }, PointerEvent::Release) => {
+            (Gesture::Idle, GestureAction::Click { at: start })
+        }
+        // A press without a release, e.g. one that happened outside the
+        // window, ends the old drag before starting over
+        (Gesture::Dragging, PointerEvent::Press { at, now }) => (
+            Gesture::Pending {
+                start: at,
+                pressed_at: now,
+            },
+            GestureAction::EndDrag,
+        ),
+        (Gesture::Dragging, PointerEvent::Move { .. }) => {
+            (Gesture::Dragging, GestureAction::ContinueDrag)
+        }
+        (Gesture::Dragging, PointerEvent::Release) => (Gesture::Idle, GestureAction::EndDrag),
+    }
+}
+
+// How many brush dispatch regions the dirty rect debug view outlines
+const DIRTY_RECT_TRAIL: usize = 64;
+
+/// Current brush settings, read by every stroke segment as it's painted.
+#[derive(Debug, Copy, Clone)]
+pub struct BrushState {
+    /// RGBA paint color.
+    pub color: [f32; 4],
+    /// Radius in canvas texels.
+    pub radius: f32,
+    /// 1.0 paints a hard disc; lower values feather the edge over that
+    /// fraction of the radius.
+    pub hardness: f32,
+}
+
+impl Default for BrushState {
+    fn default() -> Self {
+        Self {
+            color: [1.0, 0.0, 0.0, 1.0],
+            radius: 10.0,
+            hardness: 1.0,
+        }
+    }
+}
+
+impl BrushState {
+    /// Scales the radius, staying within the supported range.
+    pub fn scale_radius(&mut self, factor: f32) {
+        self.radius = (self.radius * factor).clamp(MIN_BRUSH_RADIUS, MAX_BRUSH_RADIUS);
+    }
+
+    /// Moves the hardness by `delta`, staying within [0, 1].
+    pub fn adjust_hardness(&mut self, delta: f32) {
+        self.hardness = (self.hardness + delta).clamp(0.0, 1.0);
+    }
+}
+
+/// What a left-button drag does.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Tool {
+    /// Drag pans the fractal.
+    Pan,
+    /// Drag paints onto the canvas.
+    Brush,
+    /// Drag erases the canvas with the brush's size and hardness.
+    Eraser,
+}
+
+/// The display pass's bindings: the texture it shows and a sampler.
+fn create_texture_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Texture Bind Group Layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ],
+    })
+}
+
+/// Builds the fullscreen display pipeline for a given surface format.
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader_module: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    cache: Option<&wgpu::PipelineCache>,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Render Pipeline"),
+        layout: Some(layout),
+        vertex: VertexState {
+            module: shader_module,
+            entry_point: Some("vs_main"),
+            buffers: &[],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(FragmentState {
+            module: shader_module,
+            entry_point: Some("fs_main"),
+            targets: &[Some(ColorTargetState {
+                format,
+                blend: Some(BlendState::REPLACE),
+                write_mask: ColorWrites::all(),
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants: &[("SRGB_SURFACE", format.is_srgb() as u8 as f64)],
+                ..Default::default()
+            },
+        }),
+        primitive: PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+            unclipped_depth: false,
+        },
+        depth_stencil: None,
+        multisample: MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache,
+    })
+}
+
+/// Uploads palette `index` into the palette uniform.
+fn write_palette(queue: &wgpu::Queue, palette_buffer: &wgpu::Buffer, index: usize) {
+    queue.write_buffer(
+        palette_buffer,
+        0,
+        bytemuck::cast_slice(&[PALETTES[index].to_uniform()]),
+    );
+}
+
+/// Clamps a window size to what textures on this device can be, so a
+/// huge or bogus size from the compositor can't fail texture creation.
+/// Both sides shrink by the same factor, keeping the aspect ratio. Either
+/// side may still be zero; callers deal with that.
+fn clamp_to_texture_limit(width: u32, height: u32, max_dimension: u32) -> (u32, u32) {
+    let longest = width.max(height);
+    if longest <= max_dimension {
+        return (width, height);
+    }
+    // A non-zero side stays at least one texel
+    let scale =
+        |side: u32| ((side as u64 * max_dimension as u64 / longest as u64) as u32).max(side.min(1));
+    let clamped = (scale(width), scale(height));
+    log::warn!(
+        "Window size {}x{} is over the {} texel texture limit; using {}x{}",
+        width,
+        height,
+        max_dimension,
+        clamped.0,
+        clamped.1
+    );
+    clamped
+}
+
+/// Canvas textures for a resize to `desc`'s size. The overlap of `old` is
+/// copied into the top-left corner of the first, which becomes the current
+/// canvas, so a shrink crops. Also returns whether the new size goes past
+/// `old` on either axis, leaving area with nothing in it yet.
+fn resize_canvas(
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    old: &wgpu::Texture,
+    desc: &wgpu::TextureDescriptor,
+) -> ([wgpu::Texture; 2], bool) {
+    let textures = [device.create_texture(desc), device.create_texture(desc)];
+    let overlap = wgpu::Extent3d {
+        width: old.width().min(desc.size.width),
+        height: old.height().min(desc.size.height),
+        depth_or_array_layers: 1,
+    };
+    encoder.copy_texture_to_texture(old.as_image_copy(), textures[0].as_image_copy(), overlap);
+    let grew = desc.size.width > old.width() || desc.size.height > old.height();
+    (textures, grew)
+}
+
+/// The bookmark slot a digit key selects, 1 being the first.
+fn bookmark_slot(key: KeyCode) -> Option<usize> {
+    let slot = match key {
+        KeyCode::Digit1 => 0,
+        KeyCode::Digit2 => 1,
+        KeyCode::Digit3 => 2,
+        KeyCode::Digit4 => 3,
+        KeyCode::Digit5 => 4,
+        KeyCode::Digit6 => 5,
+        KeyCode::Digit7 => 6,
+        KeyCode::Digit8 => 7,
+        KeyCode::Digit9 => 8,
+        _ => return None,
+    };
+    Some(slot)
+}
+
+/// Fifo (vsync) unless low latency is wanted and Mailbox is available.
+/// Fifo is the one mode every surface supports.
+fn preferred_present_mode(
+    capabilities: &wgpu::SurfaceCapabilities,
+    low_latency: bool,
+) -> wgpu::PresentMode {
+    if low_latency
+        && capabilities
+            .present_modes
+            .contains(&wgpu::PresentMode::Mailbox)
+    {
+        wgpu::PresentMode::Mailbox
+    } else {
+        wgpu::PresentMode::Fifo
+    }
+}
+
+/// Picks the surface format we want out of what the surface supports.
+/// Either kind shows the canvas the same; the display shader undoes the
+/// encoding of an sRGB one.
+fn preferred_surface_format(capabilities: &wgpu::SurfaceCapabilities) -> wgpu::TextureFormat {
+    capabilities
+        .formats
+        .iter()
+        .copied()
+        .find(|f| f.is_srgb())
+        .unwrap_or(capabilities.formats[0])
+}
+
+/// The format and alpha mode to reconfigure the surface with, if its
+/// capabilities no longer call for the current ones. The alpha mode is
+/// kept while it's still supported. None when nothing needs to change,
+/// or when the surface reports no formats at all (e.g. while minimized).
+fn surface_format_change(
+    capabilities: &wgpu::SurfaceCapabilities,
+    format: wgpu::TextureFormat,
+    alpha_mode: wgpu::CompositeAlphaMode,
+) -> Option<(wgpu::TextureFormat, wgpu::CompositeAlphaMode)> {
+    if capabilities.formats.is_empty() {
+        return None;
+    }
+    let preferred_format = preferred_surface_format(capabilities);
+    let preferred_alpha_mode = if capabilities.alpha_modes.contains(&alpha_mode) {
+        alpha_mode
+    } else {
+        capabilities.alpha_modes[0]
+    };
+    let changed = (preferred_format, preferred_alpha_mode) != (format, alpha_mode);
+    changed.then_some((preferred_format, preferred_alpha_mode))
+}
+
+// The preview is computed at 1/PREVIEW_DIVISOR of the window size each way
+const PREVIEW_DIVISOR: u32 = 4;
+// Frames without a view change before the full-resolution pass replaces the preview
+const REFINE_IDLE_FRAMES: u32 = 2;
+// Full-resolution renders are dispatched this many bands of rows at a
+// time, one band per frame, so no single dispatch runs long enough to
+// trip a driver timeout
+const COMPUTE_BANDS: u32 = 8;
+// Mandelbrot compute shader workgroup edge
+const WORKGROUP_SIZE: u32 = 8;
+
+/// A full-resolution render part way through its bands. The target is the
+/// canvas texture that isn't the current one; the ping-pong flips once
+/// the last band is written.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ComputeProgress {
+    params: MandelbrotParams,
+    // What the canvas will hold when done
+    view: RenderedView,
+    // Width of the dispatched region
+    width: u32,
+    // Next row to dispatch, and the row the dispatched region ends at
+    next_row: u32,
+    end_row: u32,
+    band_rows: u32,
+}
+
+/// Low-resolution canvas the fractal is computed into while the view is
+/// changing, and stretched over the window by the display pass.
+pub(crate) struct PreviewCanvas {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    render_bind_group: wgpu::BindGroup,
+}
+
+impl PreviewCanvas {
+    fn new(
+        device: &wgpu::Device,
+        window_size: [u32; 2],
+        target_usages: wgpu::TextureUsages,
+        texture_bind_group_layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Preview Texture"),
+            size: wgpu::Extent3d {
+                width: window_size[0].div_ceil(PREVIEW_DIVISOR),
+                height: window_size[1].div_ceil(PREVIEW_DIVISOR),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | target_usages,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let render_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Preview Render Bind Group"),
+            layout: texture_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        });
+
+        Self {
+            texture,
+            view,
+            render_bind_group,
+        }
+    }
+}
+
+/// A display bind group sampling a single texel of `rgba`. Drawn through
+/// the display pipeline with a scissor rect, it fills that rect with solid
+/// color, which is all the debug overlays need.
+pub(crate) fn solid_color_bind_group(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture_bind_group_layout: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    rgba: [u8; 4],
+) -> wgpu::BindGroup {
+    let size = wgpu::Extent3d {
+        width: 1,
+        height: 1,
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Solid Color Texture"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    queue.write_texture(
+        texture.as_image_copy(),
+        &rgba,
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(4),
+            rows_per_image: None,
+        },
+        size,
+    );
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Solid Color Bind Group"),
+        layout: texture_bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
+
+/// The CPU-side part of [`State`] carried over when the GPU device is
+/// lost and everything else is recreated.
+pub struct RetainedState {
+    mandelbrot_state: MandelbrotState,
+    tool: Tool,
+    brush: BrushState,
+    view_history: ViewHistory,
+    modifiers: ModifiersState,
+    visible: bool,
+    low_latency: bool,
+    submit_log: SubmitLog,
+    #[cfg(not(target_arch = "wasm32"))]
+    session_stats: Option<SessionStats>,
+}
 
 pub struct State {
-    pub surface: wgpu::Surface<'static>,
-    pub device: wgpu::Device,
-    pub queue: wgpu::Queue,
-    pub config: wgpu::SurfaceConfiguration,
-    pub is_surface_configured: bool,
-    pub render_pipeline: wgpu::RenderPipeline,
-    pub vertex_buffer: wgpu::Buffer,
-    pub num_vertices: u32,
-
-    pub mandelbrot_state: MandelbrotState,
-    pub window: Arc<Window>,
-    pub compute_pipeline: wgpu::ComputePipeline,
-
-    // Paint textures (ping-pong between them)
-    pub canvas_texture_a: wgpu::Texture,
-    pub canvas_texture_b: wgpu::Texture,
-    pub canvas_view_a: wgpu::TextureView,
-    pub canvas_view_b: wgpu::TextureView,
-    pub use_texture_a_as_input: bool,
-
-    // Compute shader resources
-    pub compute_bind_group_a_to_b: wgpu::BindGroup,
-    pub compute_bind_group_b_to_a: wgpu::BindGroup,
-    pub params_buffer: wgpu::Buffer,
-    pub sampler: wgpu::Sampler,
+    pub(crate) surface: wgpu::Surface<'static>,
+    pub(crate) adapter: wgpu::Adapter,
+    pub(crate) device: wgpu::Device,
+    pub(crate) queue: wgpu::Queue,
+    pub(crate) config: wgpu::SurfaceConfiguration,
+    pub(crate) is_surface_configured: bool,
+    pub(crate) render_pipeline: wgpu::RenderPipeline,
+    // Display pipelines by surface format, so a monitor switch back and forth is a lookup
+    pub(crate) render_pipelines: HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>,
+    pub(crate) render_pipeline_layout: wgpu::PipelineLayout,
+    pub(crate) shader_module: wgpu::ShaderModule,
+    // Saved to disk when dropped; None where the backend has no cache
+    pub(crate) pipeline_cache: Option<PipelineCache>,
+
+    pub(crate) mandelbrot_state: MandelbrotState,
+    pub(crate) window: Arc<Window>,
+    // Compute shaders, or a fragment shader where there are none
+    pub(crate) render_path: RenderPath,
+
+    // Paint textures (ping-pong between them). The one holding the latest
+    // frame is the next compute pass's input; the pass writes the other.
+    pub(crate) canvas_texture_a: wgpu::Texture,
+    pub(crate) canvas_texture_b: wgpu::Texture,
+    pub(crate) canvas_view_a: wgpu::TextureView,
+    pub(crate) canvas_view_b: wgpu::TextureView,
+    pub(crate) use_texture_a_as_input: bool,
+
+    // Fractal pass resources
+    pub(crate) params_buffer: wgpu::Buffer,
+    pub(crate) palette_buffer: wgpu::Buffer,
+    pub(crate) sampler: wgpu::Sampler,
 
     // Render resources
-    pub render_bind_group_a: wgpu::BindGroup,
-    pub render_bind_group_b: wgpu::BindGroup,
-    pub texture_bind_group_layout: wgpu::BindGroupLayout,
+    pub(crate) render_bind_group_a: wgpu::BindGroup,
+    pub(crate) render_bind_group_b: wgpu::BindGroup,
+    pub(crate) texture_bind_group_layout: wgpu::BindGroupLayout,
+
+    // Compare snapshot (held Backquote shows it instead of the live canvas)
+    pub(crate) snapshot_texture: wgpu::Texture,
+    pub(crate) snapshot_bind_group: wgpu::BindGroup,
+    pub(crate) snapshot_valid: bool,
+    pub(crate) showing_snapshot: bool,
+
+    pub(crate) modifiers: ModifiersState,
+
+    // Rendering pauses while the window or canvas can't be seen
+    pub(crate) visible: bool,
+    // Set while a redraw is pending so bursts of events queue only one frame
+    pub(crate) redraw_requested: bool,
+
+    pub(crate) present_stats: PresentStats,
+    // GPU errors attributed to the submission that raised them
+    pub(crate) submit_log: SubmitLog,
+
+    // What the current canvas texture holds, for reusing it across small pans
+    pub(crate) rendered_view: Option<RenderedView>,
+
+    // Low-resolution stand-in shown while the view is changing
+    pub(crate) preview: PreviewCanvas,
+    pub(crate) showing_preview: bool,
+    // Frames since the view last changed, counted while the preview is up
+    pub(crate) idle_frames: u32,
+    // Bands of a full-resolution render still to dispatch
+    pub(crate) compute_progress: Option<ComputeProgress>,
+
+    // Brush painting
+    pub(crate) tool: Tool,
+    pub(crate) painting: bool,
+    pub(crate) gesture: Gesture,
+    pub(crate) brush: BrushState,
+    pub(crate) history: CanvasHistory,
+    pub(crate) view_history: ViewHistory,
+    pub(crate) bookmarks: Bookmarks,
+    pub(crate) brush_params_buffer: wgpu::Buffer,
+
+    // Debug view of the regions recent brush dispatches covered
+    pub(crate) show_dirty_rects: bool,
+    // Texel rects as [min_x, min_y, max_x, max_y], oldest first
+    pub(crate) dirty_rects: VecDeque<[u32; 4]>,
+    pub(crate) outline_bind_group: wgpu::BindGroup,
+    pub(crate) hud: Hud,
+    // Prefer Mailbox over Fifo when choosing a present mode
+    pub(crate) low_latency: bool,
+    // Set from the device lost callback
+    pub(crate) device_lost: Arc<AtomicBool>,
+    // Largest texture side the device allows; window sizes are clamped to it
+    pub(crate) max_texture_dimension: u32,
+    // Recording of every fractal render, while turned on
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) session_stats: Option<SessionStats>,
+    // None when the device lacks timestamp queries
+    pub(crate) gpu_timer: Option<GpuTimer>,
 }
 
 impl State {
@@ -119,33 +879,62 @@ impl State {
             })
             .await?;
 
+        // WebGL2 has neither compute shaders nor storage textures; the
+        // fractal is drawn with a fragment shader there instead
+        let compute = RenderPath::supports_compute(&adapter);
+        if !compute {
+            log::warn!(
+                "No compute shader support; using the fragment shader path without painting"
+            );
+        }
+
         let (device, queue) = adapter
             .request_device(&wgpu::DeviceDescriptor {
                 label: None,
-                required_limits: if cfg!(target_arch = "wasm32") {
-                    wgpu::Limits::downlevel_webgl2_defaults()
-                } else {
+                required_limits: if !cfg!(target_arch = "wasm32") {
                     wgpu::Limits::default()
+                } else if compute {
+                    wgpu::Limits::downlevel_defaults()
+                } else {
+                    wgpu::Limits::downlevel_webgl2_defaults()
                 },
                 memory_hints: Default::default(),
                 trace: wgpu::Trace::Off,
-                required_features: wgpu::Features::empty(),
+                // Pass timings and the pipeline cache are niceties; WebGL2
+                // and many adapters can't do them
+                required_features: adapter.features()
+                    & (wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::PIPELINE_CACHE),
             })
             .await?;
 
+        // A driver reset or watchdog timeout loses the device; the app
+        // notices on the next frame and rebuilds everything
+        let device_lost = Arc::new(AtomicBool::new(false));
+        let lost = device_lost.clone();
+        device.set_device_lost_callback(move |reason, message| {
+            log::error!("GPU device lost ({:?}): {}", reason, message);
+            lost.store(true, Ordering::Release);
+        });
+        // Submissions have their own error scopes; anything else would panic
+        device.on_uncaptured_error(Box::new(|error| {
+            log::error!("Uncaptured GPU error: {}", error);
+        }));
+
+        let pipeline_cache = PipelineCache::load(&device, &adapter);
+
+        let max_texture_dimension = device.limits().max_texture_dimension_2d;
+        let (width, height) =
+            clamp_to_texture_limit(size.width, size.height, max_texture_dimension);
+
         let surface_capabilities = surface.get_capabilities(&adapter);
-        let surface_format = surface_capabilities
-            .formats
-            .iter()
-            .find(|f| f.is_srgb())
-            .unwrap_or(&surface_capabilities.formats[0]);
+        let surface_format = preferred_surface_format(&surface_capabilities);
 
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format.clone(),
-            width: size.width,
-            height: size.height,
-            present_mode: surface_capabilities.present_modes[0],
+            format: surface_format,
+            width,
+            height,
+            present_mode: preferred_present_mode(&surface_capabilities, false),
             alpha_mode: surface_capabilities.alpha_modes[0],
             view_formats: vec![],
             desired_maximum_frame_latency: 2,
@@ -155,8 +944,8 @@ impl State {
         let texture_desc = wgpu::TextureDescriptor {
             label: Some("Canvas Texture"),
             size: wgpu::Extent3d {
-                width: size.width.max(1),
-                height: size.height.max(1),
+                width: width.max(1),
+                height: height.max(1),
                 depth_or_array_layers: 1,
             },
             mip_level_count: 1,
@@ -164,7 +953,8 @@ impl State {
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba8Unorm,
             usage: wgpu::TextureUsages::TEXTURE_BINDING
-                | wgpu::TextureUsages::STORAGE_BINDING
+                | RenderPath::target_usages(compute)
+                | wgpu::TextureUsages::COPY_SRC
                 | wgpu::TextureUsages::COPY_DST,
             view_formats: &[],
         };
@@ -175,6 +965,12 @@ impl State {
         let canvas_view_a = canvas_texture_a.create_view(&wgpu::TextureViewDescriptor::default());
         let canvas_view_b = canvas_texture_b.create_view(&wgpu::TextureViewDescriptor::default());
 
+        let snapshot_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Snapshot Texture"),
+            ..texture_desc
+        });
+        let snapshot_view = snapshot_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
         // Textures are initialized to zero by default
 
         // Create sampler
@@ -188,21 +984,6 @@ impl State {
             ..Default::default()
         });
 
-        // Create compute shader
-        let compute_shader_module = device.create_shader_module(ShaderModuleDescriptor {
-            label: Some("Compute Shader"),
-            source: ShaderSource::Wgsl(include_str!("compute.wgsl").into()),
-        });
-
-        let compute_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
-            label: Some("Compute Pipeline"),
-            layout: None,
-            module: &compute_shader_module,
-            entry_point: Some("compute"),
-            compilation_options: wgpu::PipelineCompilationOptions::default(),
-            cache: None,
-        });
-
         // Create uniform buffer for compute parameters
         let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Compute Params Buffer"),
@@ -211,37 +992,18 @@ impl State {
             mapped_at_creation: false,
         });
 
-        // Create compute bind groups
-        let compute_bind_group_layout = compute_pipeline.get_bind_group_layout(0);
-
-        let compute_bind_group_a_to_b = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Mandelbrot Compute Bind Group A"),
-            layout: &compute_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&canvas_view_a),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: params_buffer.as_entire_binding(),
-                },
-            ],
+        // Gradient the escape time is colored through
+        let palette_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Palette Buffer"),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            contents: bytemuck::cast_slice(&[PALETTES[0].to_uniform()]),
         });
 
-        let compute_bind_group_b_to_a = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Mandelbrot Compute Bind Group B"),
-            layout: &compute_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&canvas_view_b),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: params_buffer.as_entire_binding(),
-                },
-            ],
+        let brush_params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Brush Params Buffer"),
+            size: std::mem::size_of::<BrushParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
         });
 
         // Create render pipeline
@@ -250,28 +1012,7 @@ impl State {
             source: ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
         });
 
-        let texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("Texture Bind Group Layout"),
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
-            });
+        let texture_bind_group_layout = create_texture_bind_group_layout(&device);
 
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -280,50 +1021,15 @@ impl State {
                 push_constant_ranges: &[],
             });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: VertexState {
-                module: &shader_module,
-                entry_point: Some("vs_main"),
-                buffers: &[shader::Vertex::vertex_buffer_desc()],
-                compilation_options: Default::default(),
-            },
-            fragment: Some(FragmentState {
-                module: &shader_module,
-                entry_point: Some("fs_main"),
-                targets: &[Some(ColorTargetState {
-                    format: config.format,
-                    blend: Some(BlendState::REPLACE),
-                    write_mask: ColorWrites::all(),
-                })],
-                compilation_options: Default::default(),
-            }),
-            primitive: PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-                unclipped_depth: false,
-            },
-            depth_stencil: None,
-            multisample: MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-            cache: None,
-        });
-
-        // Create vertex buffer for fullscreen quad
-        let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            usage: BufferUsages::VERTEX,
-            contents: bytemuck::cast_slice(QUAD_VERTICES),
-        });
+        let render_pipeline = create_render_pipeline(
+            &device,
+            &render_pipeline_layout,
+            &shader_module,
+            config.format,
+            pipeline_cache.as_ref().map(PipelineCache::cache),
+        );
+        let mut render_pipelines = HashMap::new();
+        render_pipelines.insert(config.format, render_pipeline.clone());
 
         // Create render bind groups
         let render_bind_group_a = device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -356,40 +1062,160 @@ impl State {
             ],
         });
 
+        let snapshot_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Snapshot Bind Group"),
+            layout: &texture_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&snapshot_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        let outline_bind_group = solid_color_bind_group(
+            &device,
+            &queue,
+            &texture_bind_group_layout,
+            &sampler,
+            [255, 0, 255, 255],
+        );
+        let hud = Hud::new(HudColors {
+            on_time: solid_color_bind_group(
+                &device,
+                &queue,
+                &texture_bind_group_layout,
+                &sampler,
+                [64, 220, 96, 255],
+            ),
+            missed: solid_color_bind_group(
+                &device,
+                &queue,
+                &texture_bind_group_layout,
+                &sampler,
+                [235, 64, 52, 255],
+            ),
+            budget: solid_color_bind_group(
+                &device,
+                &queue,
+                &texture_bind_group_layout,
+                &sampler,
+                [255, 255, 255, 255],
+            ),
+        });
+
+        let gpu_timer = GpuTimer::new(&device, &queue);
+
+        let preview = PreviewCanvas::new(
+            &device,
+            [width.max(1), height.max(1)],
+            RenderPath::target_usages(compute),
+            &texture_bind_group_layout,
+            &sampler,
+        );
+
+        let render_path = RenderPath::new(
+            &device,
+            compute,
+            &CanvasBindings {
+                canvas_view_a: &canvas_view_a,
+                canvas_view_b: &canvas_view_b,
+                preview_view: &preview.view,
+                params_buffer: &params_buffer,
+                palette_buffer: &palette_buffer,
+                brush_params_buffer: &brush_params_buffer,
+            },
+            pipeline_cache.as_ref().map(PipelineCache::cache),
+        );
+
+        let present_stats = PresentStats::new(
+            window
+                .current_monitor()
+                .and_then(|monitor| monitor.refresh_rate_millihertz()),
+        );
+
         Ok(Self {
             surface,
+            adapter,
             device,
             queue,
             config,
             is_surface_configured: false,
             render_pipeline,
-            vertex_buffer,
-            num_vertices: QUAD_VERTICES.len() as u32,
+            render_pipelines,
+            render_pipeline_layout,
+            shader_module,
+            pipeline_cache,
             mandelbrot_state: Default::default(),
             window,
-            compute_pipeline,
+            render_path,
             canvas_texture_a,
             canvas_texture_b,
             canvas_view_a,
             canvas_view_b,
             use_texture_a_as_input: true,
-            compute_bind_group_a_to_b,
-            compute_bind_group_b_to_a,
             params_buffer,
+            palette_buffer,
             sampler,
             render_bind_group_a,
             render_bind_group_b,
             texture_bind_group_layout,
+            snapshot_texture,
+            snapshot_bind_group,
+            snapshot_valid: false,
+            showing_snapshot: false,
+            modifiers: ModifiersState::empty(),
+            visible: true,
+            redraw_requested: false,
+            present_stats,
+            submit_log: SubmitLog::default(),
+            rendered_view: None,
+            preview,
+            showing_preview: false,
+            idle_frames: 0,
+            compute_progress: None,
+            tool: Tool::Pan,
+            painting: false,
+            gesture: Gesture::Idle,
+            brush: BrushState::default(),
+            history: CanvasHistory::default(),
+            view_history: ViewHistory::default(),
+            bookmarks: Bookmarks::load(),
+            brush_params_buffer,
+            show_dirty_rects: false,
+            dirty_rects: VecDeque::new(),
+            outline_bind_group,
+            hud,
+            low_latency: false,
+            device_lost,
+            max_texture_dimension,
+            #[cfg(not(target_arch = "wasm32"))]
+            session_stats: None,
+            gpu_timer,
         })
     }
 
     pub fn resize(&mut self, width: u32, height: u32) {
+        let (width, height) = clamp_to_texture_limit(width, height, self.max_texture_dimension);
+        // Minimizing reports a zero size. The surface and canvas keep the
+        // last good size, which is what comes back on restore.
         if width > 0 && height > 0 {
             self.config.width = width;
             self.config.height = height;
             self.surface.configure(&self.device, &self.config);
             self.is_surface_configured = true;
 
+            // A resize often means the window moved to another monitor
+            self.present_stats.set_refresh_rate(
+                self.window
+                    .current_monitor()
+                    .and_then(|monitor| monitor.refresh_rate_millihertz()),
+            );
+
             // Recreate textures with new size
             let texture_desc = wgpu::TextureDescriptor {
                 label: Some("Canvas Texture"),
@@ -403,13 +1229,30 @@ impl State {
                 dimension: wgpu::TextureDimension::D2,
                 format: wgpu::TextureFormat::Rgba8Unorm,
                 usage: wgpu::TextureUsages::TEXTURE_BINDING
-                    | wgpu::TextureUsages::STORAGE_BINDING
+                    | RenderPath::target_usages(self.render_path.is_compute())
+                    | wgpu::TextureUsages::COPY_SRC
                     | wgpu::TextureUsages::COPY_DST,
                 view_formats: &[],
             };
 
-            self.canvas_texture_a = self.device.create_texture(&texture_desc);
-            self.canvas_texture_b = self.device.create_texture(&texture_desc);
+            // Carry the canvas over into the new current texture (A)
+            self.begin_submission();
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Resize Encoder"),
+                });
+            let ([canvas_texture_a, canvas_texture_b], grew) = resize_canvas(
+                &self.device,
+                &mut encoder,
+                self.current_canvas(),
+                &texture_desc,
+            );
+            self.submit(encoder, "resize", vec!["preserve copy"], Vec::new());
+
+            self.canvas_texture_a = canvas_texture_a;
+            self.canvas_texture_b = canvas_texture_b;
+            self.use_texture_a_as_input = true;
             self.canvas_view_a = self
                 .canvas_texture_a
                 .create_view(&wgpu::TextureViewDescriptor::default());
@@ -417,44 +1260,52 @@ impl State {
                 .canvas_texture_b
                 .create_view(&wgpu::TextureViewDescriptor::default());
 
-            // Textures are initialized to zero by default
-            // Trigger an update since we have new textures
-            self.mandelbrot_state.needs_update = true;
+            // The snapshot no longer matches the canvas size, so drop it
+            self.snapshot_texture = self.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Snapshot Texture"),
+                ..texture_desc
+            });
+            let snapshot_view = self
+                .snapshot_texture
+                .create_view(&wgpu::TextureViewDescriptor::default());
+            self.snapshot_valid = false;
+            // History textures are the old size
+            self.history.clear();
+            self.show_snapshot(false);
+            self.dirty_rects.clear();
+
+            // The kept image no longer lines up with a view of the new size,
+            // so the next view change recomputes everything. A shrink keeps
+            // the cropped canvas on screen until then; a grow has blank area
+            // to fill, so it recomputes the view at the new size now.
+            self.rendered_view = None;
+            if grew {
+                self.mandelbrot_state.needs_update = true;
+            }
+            self.showing_preview = false;
+            self.compute_progress = None;
+            self.request_redraw();
 
             // Recreate bind groups
-            let compute_bind_group_layout = self.compute_pipeline.get_bind_group_layout(0);
-
-            self.compute_bind_group_a_to_b =
-                self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    label: Some("Mandelbrot Compute Bind Group A"),
-                    layout: &compute_bind_group_layout,
-                    entries: &[
-                        wgpu::BindGroupEntry {
-                            binding: 0,
-                            resource: wgpu::BindingResource::TextureView(&self.canvas_view_a),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 1,
-                            resource: self.params_buffer.as_entire_binding(),
-                        },
-                    ],
-                });
-
-            self.compute_bind_group_b_to_a =
-                self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    label: Some("Mandelbrot Compute Bind Group B"),
-                    layout: &compute_bind_group_layout,
-                    entries: &[
-                        wgpu::BindGroupEntry {
-                            binding: 0,
-                            resource: wgpu::BindingResource::TextureView(&self.canvas_view_b),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 1,
-                            resource: self.params_buffer.as_entire_binding(),
-                        },
-                    ],
-                });
+            self.preview = PreviewCanvas::new(
+                &self.device,
+                [width, height],
+                RenderPath::target_usages(self.render_path.is_compute()),
+                &self.texture_bind_group_layout,
+                &self.sampler,
+            );
+
+            self.render_path.rebind(
+                &self.device,
+                &CanvasBindings {
+                    canvas_view_a: &self.canvas_view_a,
+                    canvas_view_b: &self.canvas_view_b,
+                    preview_view: &self.preview.view,
+                    params_buffer: &self.params_buffer,
+                    palette_buffer: &self.palette_buffer,
+                    brush_params_buffer: &self.brush_params_buffer,
+                },
+            );
 
             self.render_bind_group_a = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
                 label: Some("Render Bind Group A"),
@@ -485,59 +1336,79 @@ impl State {
                     },
                 ],
             });
+
+            self.snapshot_bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Snapshot Bind Group"),
+                layout: &self.texture_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&snapshot_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                ],
+            });
         }
     }
 
     pub fn render(&mut self) -> Result<(), SurfaceError> {
+        self.redraw_requested = false;
+
         if !self.is_surface_configured {
             return Ok(());
         }
 
-        self.window.request_redraw();
-
-        let output = self.surface.get_current_texture()?;
+        let output = match self.surface.get_current_texture() {
+            Ok(output) => output,
+            Err(e) => {
+                self.present_stats.record_error(&e);
+                // Try again next frame; Lost and Outdated also reconfigure
+                self.request_redraw();
+                return Err(e);
+            }
+        };
+        if output.suboptimal {
+            self.present_stats.record_suboptimal();
+        }
         let view = output
             .texture
             .create_view(&TextureViewDescriptor::default());
 
+        self.begin_submission();
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
-
-        // Run compute shader to generate Mandelbrot set
+        let mut passes = Vec::new();
+        let mut uniform_hashes = Vec::new();
+
+        // Run compute shader to generate Mandelbrot set. Changes are
+        // previewed at low resolution; the full-resolution pass follows once
+        // the view has been still for a few frames, a band per frame.
+        let mut computed = false;
+        // Whether a compute pass ran at all, into the canvas or the preview
+        let mut dispatched = false;
         if self.mandelbrot_state.needs_update {
-            let params = MandelbrotParams {
-                center: self.mandelbrot_state.center,
-                zoom: self.mandelbrot_state.zoom,
-                max_iterations: self.mandelbrot_state.max_iterations,
-                _padding: [0.0; 2],
-            };
-
-            self.queue
-                .write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
-
-            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
-                label: Some("Mandelbrot Compute Pass"),
-                timestamp_writes: None,
-            });
-
-            compute_pass.set_pipeline(&self.compute_pipeline);
-
-            // Use texture A for output
-            compute_pass.set_bind_group(0, &self.compute_bind_group_a_to_b, &[]);
-
-            // Dispatch compute shader
-            let workgroup_size = 8;
-            let window_size = self.window.inner_size();
-            let dispatch_x = (window_size.width + workgroup_size - 1) / workgroup_size;
-            let dispatch_y = (window_size.height + workgroup_size - 1) / workgroup_size;
-
-            compute_pass.dispatch_workgroups(dispatch_x, dispatch_y, 1);
-            drop(compute_pass);
-
+            // Bands of the old view are no use any more
+            self.compute_progress = None;
+            computed = self.encode_fractal(&mut encoder, &mut passes, &mut uniform_hashes, true);
+            dispatched = true;
             self.mandelbrot_state.needs_update = false;
+            self.idle_frames = 0;
+        } else if self.compute_progress.is_some() {
+            computed = self.encode_band(&mut encoder, &mut passes, &mut uniform_hashes);
+            dispatched = true;
+        } else if self.showing_preview {
+            self.idle_frames += 1;
+            if self.idle_frames >= REFINE_IDLE_FRAMES {
+                computed =
+                    self.encode_fractal(&mut encoder, &mut passes, &mut uniform_hashes, false);
+                dispatched = true;
+            }
         }
 
         // Render the current canvas texture to screen
@@ -559,73 +1430,2410 @@ impl State {
             })],
             depth_stencil_attachment: None,
             occlusion_query_set: None,
-            timestamp_writes: None,
+            timestamp_writes: self.gpu_timer.as_ref().map(GpuTimer::render_writes),
         });
 
         render_pass.set_pipeline(&self.render_pipeline);
 
-        // Always render texture A since that's where we compute the Mandelbrot set
-        let render_bind_group = &self.render_bind_group_a;
+        // Show whichever texture was written last, unless the compare key is
+        // held and a snapshot exists. A banded pan shows its target filling
+        // in; a banded refine shows its finished rows over the preview.
+        let sample_a = self.use_texture_a_as_input != computed;
+        let target_bind_group = if self.use_texture_a_as_input {
+            &self.render_bind_group_b
+        } else {
+            &self.render_bind_group_a
+        };
+        let render_bind_group = match display_source(
+            self.showing_snapshot,
+            self.showing_preview,
+            self.compute_progress.is_some(),
+            sample_a,
+        ) {
+            DisplaySource::Snapshot => &self.snapshot_bind_group,
+            DisplaySource::Preview => &self.preview.render_bind_group,
+            DisplaySource::BandTarget => target_bind_group,
+            DisplaySource::Canvas { a: true } => &self.render_bind_group_a,
+            DisplaySource::Canvas { a: false } => &self.render_bind_group_b,
+        };
 
         render_pass.set_bind_group(0, render_bind_group, &[]);
-        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        render_pass.draw(0..self.num_vertices, 0..1);
+        // One fullscreen triangle, generated in the vertex shader
+        render_pass.draw(0..3, 0..1);
+
+        match self.compute_progress {
+            Some(progress) if self.showing_preview && !self.showing_snapshot => {
+                // Everything outside the rows still to dispatch is finished
+                let height = self.config.height;
+                let pending_start = progress.next_row.min(height);
+                let pending_end = progress.end_row.min(height);
+                render_pass.set_bind_group(0, target_bind_group, &[]);
+                for (y, h) in [(0, pending_start), (pending_end, height - pending_end)] {
+                    if h > 0 {
+                        render_pass.set_scissor_rect(0, y, self.config.width, h);
+                        render_pass.draw(0..3, 0..1);
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        if self.show_dirty_rects && !self.dirty_rects.is_empty() {
+            self.draw_dirty_rects(&mut render_pass);
+        }
+        self.hud.draw(
+            &mut render_pass,
+            &self.present_stats,
+            [self.config.width, self.config.height],
+        );
 
         drop(render_pass);
+        passes.push("display");
 
-        self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
+        if let Some(timer) = &mut self.gpu_timer {
+            // Lets finished readbacks report in before looking for a free one
+            let _ = self.device.poll(wgpu::PollType::Poll);
+            timer.resolve(&mut encoder, self.present_stats.presents, dispatched);
+            passes.push("timestamp resolve");
+        }
+
+        self.submit(encoder, "frame", passes, uniform_hashes);
+        if let Some(timer) = &mut self.gpu_timer {
+            timer.after_submit();
+        }
+
+        // The texture just written becomes the next pass's input
+        if computed {
+            self.use_texture_a_as_input = !self.use_texture_a_as_input;
+        }
+        debug_assert_eq!(
+            sample_a, self.use_texture_a_as_input,
+            "displayed canvas texture must be the one the next compute pass reads"
+        );
+        self.present_stats.record_present(web_time::Instant::now());
+        output.present();
 
-        // Update cursor position
-        self.mandelbrot_state.prev_cursor_location = self.mandelbrot_state.cursor_location;
+        // Input and commands request frames as they happen; only unfinished
+        // work keeps frames coming on its own
+        if self.needs_redraw() {
+            self.request_redraw();
+        }
 
         Ok(())
     }
 
+    /// Whether the GPU device is gone and State has to be rebuilt.
+    pub fn is_device_lost(&self) -> bool {
+        self.device_lost.load(Ordering::Acquire)
+    }
+
+    /// Tears down everything on the GPU side, keeping what should survive
+    /// a device loss: the view, tool and brush settings and history of
+    /// views. Paint lives on the GPU and is lost.
+    pub fn into_retained(self) -> RetainedState {
+        RetainedState {
+            mandelbrot_state: self.mandelbrot_state,
+            tool: self.tool,
+            brush: self.brush,
+            view_history: self.view_history,
+            modifiers: self.modifiers,
+            visible: self.visible,
+            low_latency: self.low_latency,
+            submit_log: self.submit_log,
+            #[cfg(not(target_arch = "wasm32"))]
+            session_stats: self.session_stats,
+        }
+    }
+
+    /// Picks up where a lost State left off and recomputes the fractal.
+    pub fn restore(&mut self, retained: RetainedState) {
+        self.mandelbrot_state = retained.mandelbrot_state;
+        self.mandelbrot_state.dragging = false;
+        // The new palette buffer starts out with the first one
+        self.set_palette(self.mandelbrot_state.palette);
+        // The new adapter may be one without the brush
+        if self.render_path.is_compute() {
+            self.tool = retained.tool;
+        }
+        self.brush = retained.brush;
+        self.view_history = retained.view_history;
+        self.modifiers = retained.modifiers;
+        self.visible = retained.visible;
+        self.submit_log = retained.submit_log;
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.session_stats = retained.session_stats;
+        }
+        if retained.low_latency {
+            self.apply_command(Command::ToggleLowLatency);
+        }
+        self.view_changed();
+    }
+
+    /// Whether there's work left that another frame would do: a recompute,
+    /// drag motion not yet applied, a preview still to be refined or bands
+    /// still to dispatch. Otherwise the event loop sleeps until the next
+    /// input.
+    pub fn needs_redraw(&self) -> bool {
+        self.mandelbrot_state.needs_update
+            || self.mandelbrot_state.pan_delta != [0.0, 0.0]
+            || self.showing_preview
+            || self.compute_progress.is_some()
+    }
+
+    /// Encodes the compute pass for the current view. A full recompute goes
+    /// to the low-resolution preview when `allow_preview` is set; small pans
+    /// and refinement write the canvas, starting with its first band.
+    /// Returns whether the canvas was completely written, so the caller can
+    /// flip the ping-pong textures.
+    fn encode_fractal(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        passes: &mut Vec<&'static str>,
+        uniform_hashes: &mut Vec<(&'static str, u64)>,
+        allow_preview: bool,
+    ) -> bool {
+        let size = [
+            self.current_canvas().width(),
+            self.current_canvas().height(),
+        ];
+        let mut view = RenderedView {
+            center: self.mandelbrot_state.center,
+            zoom: self.mandelbrot_state.zoom,
+            stretch: self.mandelbrot_state.stretch,
+            max_iterations: self.mandelbrot_state.max_iterations,
+            debug_heatmap: self.mandelbrot_state.debug_heatmap,
+            smooth_coloring: self.mandelbrot_state.smooth_coloring,
+            palette: self.mandelbrot_state.palette,
+            size,
+        };
+
+        // A small pan only needs the newly exposed strips computed
+        let reuse = self
+            .rendered_view
+            .and_then(|prev| pan_reuse::plan_pan_reuse(&prev, &view));
+        let preview = allow_preview && reuse.is_none();
+        let kind = match reuse {
+            _ if preview => "preview",
+            Some(_) => "pan",
+            None => "full",
+        };
+        let regions = match reuse {
+            Some(reuse) => {
+                view.center = reuse.center;
+                let regions = pan_reuse::shift_regions(size, reuse.shift);
+                self.shift_canvas(encoder, &regions);
+                passes.push("pan reuse copy");
+                regions
+            }
+            // Shifting by a whole screen keeps nothing and recomputes everything
+            None => pan_reuse::shift_regions(size, [size[0] as i32, size[1] as i32]),
+        };
+
+        // The preview covers the same view with fewer pixels, all computed
+        let image_size = if preview {
+            [self.preview.texture.width(), self.preview.texture.height()]
+        } else {
+            size
+        };
+        let (origin, reuse_min, reuse_max, dispatch_size) = if preview {
+            ([0, 0], [0, 0], [0, 0], image_size)
+        } else {
+            (
+                regions.dispatch_origin,
+                regions.copy_dst,
+                [
+                    regions.copy_dst[0] + regions.copy_size[0],
+                    regions.copy_dst[1] + regions.copy_size[1],
+                ],
+                regions.dispatch_size,
+            )
+        };
+
+        let params = MandelbrotParams {
+            center: view.center,
+            zoom: view.zoom,
+            max_iterations: view.max_iterations,
+            debug_heatmap: view.debug_heatmap as u32,
+            stretch: view.stretch,
+            origin,
+            reuse_min,
+            reuse_max,
+            smooth_coloring: view.smooth_coloring as u32,
+            _padding0: 0,
+            tile_offset: [0, 0],
+            image_size,
+            _padding1: [0; 2],
+        };
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(stats) = &mut self.session_stats {
+            stats.record(
+                self.present_stats.presents,
+                view.center,
+                view.zoom,
+                view.max_iterations,
+                kind,
+            );
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = kind;
+
+        if preview {
+            self.dispatch_mandelbrot(
+                encoder,
+                passes,
+                uniform_hashes,
+                &params,
+                dispatch_size,
+                true,
+            );
+            self.showing_preview = true;
+            // The canvas still holds the last full-resolution view
+            return false;
+        }
+
+        // The target only matches a view once every band is in
+        self.rendered_view = None;
+        let band_rows = size[1]
+            .div_ceil(COMPUTE_BANDS)
+            .div_ceil(WORKGROUP_SIZE)
+            .max(1)
+            * WORKGROUP_SIZE;
+        self.compute_progress = Some(ComputeProgress {
+            params,
+            view,
+            width: dispatch_size[0],
+            next_row: origin[1],
+            end_row: origin[1] + dispatch_size[1],
+            band_rows,
+        });
+        self.encode_band(encoder, passes, uniform_hashes)
+    }
+
+    /// Encodes the next band of the full-resolution render in progress.
+    /// Returns whether that was the last one, leaving the canvas written.
+    fn encode_band(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        passes: &mut Vec<&'static str>,
+        uniform_hashes: &mut Vec<(&'static str, u64)>,
+    ) -> bool {
+        let Some(mut progress) = self.compute_progress else {
+            return false;
+        };
+        let rows = progress
+            .band_rows
+            .min(progress.end_row.saturating_sub(progress.next_row));
+        let mut params = progress.params;
+        params.origin[1] = progress.next_row;
+        self.dispatch_mandelbrot(
+            encoder,
+            passes,
+            uniform_hashes,
+            &params,
+            [progress.width, rows],
+            false,
+        );
+
+        progress.next_row += rows;
+        if progress.next_row < progress.end_row {
+            self.compute_progress = Some(progress);
+            return false;
+        }
+        self.compute_progress = None;
+        self.showing_preview = false;
+        self.rendered_view = Some(progress.view);
+        true
+    }
+
+    // One Mandelbrot compute pass over `size` pixels from `params.origin`,
+    // into the preview or the canvas texture that isn't the current one
+    fn dispatch_mandelbrot(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        passes: &mut Vec<&'static str>,
+        uniform_hashes: &mut Vec<(&'static str, u64)>,
+        params: &MandelbrotParams,
+        size: [u32; 2],
+        preview: bool,
+    ) {
+        self.queue
+            .write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(params));
+        uniform_hashes.push((
+            "params",
+            submit_log::hash_uniform(bytemuck::bytes_of(params)),
+        ));
+        passes.push(if preview {
+            "mandelbrot preview"
+        } else {
+            "mandelbrot"
+        });
+
+        // Write the texture that isn't holding the previous frame
+        let compute = match &self.render_path {
+            RenderPath::Compute(compute) => compute,
+            RenderPath::Fragment(fragment) => {
+                let target = if preview {
+                    &self.preview.texture
+                } else {
+                    self.next_canvas()
+                };
+                fragment.encode(
+                    encoder,
+                    target,
+                    &fragment.bind_group,
+                    [params.origin[0], params.origin[1], size[0], size[1]],
+                    self.gpu_timer.as_ref().map(GpuTimer::fragment_writes),
+                );
+                return;
+            }
+        };
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Mandelbrot Compute Pass"),
+            timestamp_writes: self.gpu_timer.as_ref().map(GpuTimer::compute_writes),
+        });
+
+        compute_pass.set_pipeline(&compute.pipeline);
+
+        let compute_bind_group = if preview {
+            &compute.bind_groups.preview
+        } else if self.use_texture_a_as_input {
+            &compute.bind_groups.a_to_b
+        } else {
+            &compute.bind_groups.b_to_a
+        };
+        compute_pass.set_bind_group(0, compute_bind_group, &[]);
+
+        // Dispatch compute shader over the region that needs computing
+        let dispatch_x = size[0].div_ceil(WORKGROUP_SIZE);
+        let dispatch_y = size[1].div_ceil(WORKGROUP_SIZE);
+
+        if dispatch_x > 0 && dispatch_y > 0 {
+            compute_pass.dispatch_workgroups(dispatch_x, dispatch_y, 1);
+        }
+    }
+
+    /// Outlines the recent brush dispatch regions on top of the display
+    /// pass. Canvas texels map one to one onto surface pixels.
+    fn draw_dirty_rects(&self, render_pass: &mut wgpu::RenderPass) {
+        render_pass.set_bind_group(0, &self.outline_bind_group, &[]);
+        let bounds = [self.config.width, self.config.height];
+        for &[min_x, min_y, max_x, max_y] in &self.dirty_rects {
+            let max_x = max_x.min(bounds[0]);
+            let max_y = max_y.min(bounds[1]);
+            if min_x >= max_x || min_y >= max_y {
+                continue;
+            }
+            let (width, height) = (max_x - min_x, max_y - min_y);
+            // Top, bottom, left and right edges, one pixel thick
+            for (x, y, w, h) in [
+                (min_x, min_y, width, 1),
+                (min_x, max_y - 1, width, 1),
+                (min_x, min_y, 1, height),
+                (max_x - 1, min_y, 1, height),
+            ] {
+                render_pass.set_scissor_rect(x, y, w, h);
+                render_pass.draw(0..3, 0..1);
+            }
+        }
+    }
+
+    /// Computes the full-resolution canvas now if only the preview, or
+    /// some of its bands, are done, for anything that reads or paints the
+    /// canvas. Each band is still its own submission.
+    fn finish_preview(&mut self) {
+        if !self.showing_preview && self.compute_progress.is_none() {
+            return;
+        }
+        loop {
+            self.begin_submission();
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Refine Encoder"),
+                });
+            let mut passes = Vec::new();
+            let mut uniform_hashes = Vec::new();
+            let done = if self.compute_progress.is_some() {
+                self.encode_band(&mut encoder, &mut passes, &mut uniform_hashes)
+            } else {
+                self.encode_fractal(&mut encoder, &mut passes, &mut uniform_hashes, false)
+            };
+            self.submit(encoder, "refine", passes, uniform_hashes);
+            if done {
+                break;
+            }
+        }
+        self.use_texture_a_as_input = !self.use_texture_a_as_input;
+        self.request_redraw();
+    }
+
     pub fn handle_key(&mut self, event_loop: &ActiveEventLoop, key: KeyCode, is_pressed: bool) {
+        // Compare is a held key, so it needs to see releases too
+        if key == KeyCode::Backquote {
+            if is_pressed && self.modifiers.control_key() {
+                self.apply_command(Command::TakeSnapshot);
+            } else {
+                self.apply_command(Command::ShowSnapshot(is_pressed));
+            }
+            return;
+        }
+
         if !is_pressed {
             return;
         }
 
-        match key {
-            KeyCode::Escape => event_loop.exit(),
-            KeyCode::KeyR => {
-                // Reset to default view
+        if key == KeyCode::Escape {
+            event_loop.exit();
+        } else if let Some(command) = self.key_command(key) {
+            self.apply_command(command);
+        }
+    }
+
+    /// The command a key press stands for, given the current modifiers.
+    fn key_command(&self, key: KeyCode) -> Option<Command> {
+        let shift = self.modifiers.shift_key();
+        let command = match key {
+            // Frame time graph and stats in the title
+            KeyCode::F3 => Command::ToggleHud,
+            // Debug view of per-pixel iteration cost
+            KeyCode::F4 => Command::ToggleHeatmap,
+            // Debug view of the regions brush dispatches covered
+            KeyCode::F6 => Command::ToggleDirtyRects,
+            KeyCode::F8 => Command::ToggleSessionStats,
+            KeyCode::F9 => Command::CopyDiagnostics,
+            // Switch between banded and smooth coloring
+            KeyCode::KeyC => Command::ToggleSmoothColoring,
+            // Undo any anamorphic stretch
+            KeyCode::Backslash => Command::ResetStretch,
+            KeyCode::KeyZ if self.modifiers.control_key() && shift => Command::Redo,
+            KeyCode::KeyZ if self.modifiers.control_key() => Command::Undo,
+            KeyCode::KeyP => Command::CyclePalette,
+            KeyCode::KeyS => Command::Screenshot,
+            KeyCode::ArrowLeft if self.modifiers.alt_key() => Command::HistoryBack,
+            KeyCode::ArrowRight if self.modifiers.alt_key() => Command::HistoryForward,
+            // Shift+arrows move the view, Ctrl+Shift+arrows in finer steps.
+            // Moving the view right moves the content left.
+            KeyCode::ArrowLeft | KeyCode::ArrowRight | KeyCode::ArrowUp | KeyCode::ArrowDown
+                if shift =>
+            {
+                let step = if self.modifiers.control_key() {
+                    KEY_PAN_STEP / 10.0
+                } else {
+                    KEY_PAN_STEP
+                };
+                let (dx, dy) = match key {
+                    KeyCode::ArrowLeft => (step, 0.0),
+                    KeyCode::ArrowRight => (-step, 0.0),
+                    KeyCode::ArrowUp => (0.0, step),
+                    _ => (0.0, -step),
+                };
+                Command::Pan { dx, dy }
+            }
+            KeyCode::KeyE if self.modifiers.control_key() => Command::Export {
+                width: self.config.width * EXPORT_SCALE,
+                height: self.config.height * EXPORT_SCALE,
+            },
+            KeyCode::BracketLeft if shift => Command::AdjustBrushHardness(-BRUSH_HARDNESS_STEP),
+            KeyCode::BracketRight if shift => Command::AdjustBrushHardness(BRUSH_HARDNESS_STEP),
+            KeyCode::BracketLeft => Command::ScaleBrushRadius(1.0 / BRUSH_RADIUS_STEP),
+            KeyCode::BracketRight => Command::ScaleBrushRadius(BRUSH_RADIUS_STEP),
+            KeyCode::KeyB => Command::SetTool(Tool::Brush),
+            // E toggles between erasing and painting
+            KeyCode::KeyE if self.tool == Tool::Eraser => Command::SetTool(Tool::Brush),
+            KeyCode::KeyE => Command::SetTool(Tool::Eraser),
+            KeyCode::KeyH => Command::SetTool(Tool::Pan),
+            KeyCode::KeyR => Command::ResetView,
+            KeyCode::Equal | KeyCode::NumpadAdd => Command::ZoomBy(1.5),
+            KeyCode::Minus | KeyCode::NumpadSubtract => Command::ZoomBy(1.0 / 1.5),
+            KeyCode::ArrowUp => Command::AdjustIterations(ITERATIONS_STEP),
+            KeyCode::ArrowDown => Command::AdjustIterations(-ITERATIONS_STEP),
+            KeyCode::KeyA => Command::ToggleAutoIterations,
+            KeyCode::KeyV if shift => Command::ToggleLowLatency,
+            KeyCode::KeyV => Command::CyclePresentMode,
+            KeyCode::KeyM => Command::SnapToMinibrot,
+            _ => {
+                let slot = bookmark_slot(key)?;
+                if self.modifiers.control_key() {
+                    Command::SaveBookmark(slot)
+                } else {
+                    Command::GoToBookmark(slot)
+                }
+            }
+        };
+        Some(command)
+    }
+
+    /// Performs a command. Input handlers go through here too, so a script
+    /// replaying commands gets exactly what the user would have.
+    pub fn apply_command(&mut self, command: Command) {
+        match command {
+            Command::SetCenter(center) => {
+                self.mandelbrot_state.center = [center[0] as f32, center[1] as f32];
+                self.view_changed();
+            }
+            Command::SetZoom(zoom) => {
+                if self.mandelbrot_state.set_zoom(zoom) {
+                    self.view_changed();
+                }
+            }
+            Command::ZoomBy(factor) => {
+                if self.mandelbrot_state.zoom_by(factor) {
+                    self.view_changed();
+                }
+            }
+            Command::ZoomAtCursor(factor) => {
+                let aspect_ratio = self.aspect_ratio();
+                self.mandelbrot_state.zoom_at_cursor(factor, aspect_ratio);
+                self.view_changed();
+            }
+            Command::SetMaxIterations(max_iterations) => {
+                self.mandelbrot_state.auto_iterations = false;
+                // The shader divides by it
+                self.mandelbrot_state.max_iterations = max_iterations.max(1);
+                self.view_changed();
+            }
+            Command::AdjustIterations(delta) => {
+                let mandelbrot_state = &mut self.mandelbrot_state;
+                if mandelbrot_state.auto_iterations {
+                    // Shift the automatic value; update() picks it up
+                    mandelbrot_state.auto_iterations_offset += delta;
+                    self.request_redraw();
+                } else {
+                    let adjusted = mandelbrot_state.max_iterations as i64 + delta as i64;
+                    mandelbrot_state.max_iterations =
+                        adjusted.clamp(MIN_ITERATIONS as i64, u32::MAX as i64) as u32;
+                    self.view_changed();
+                }
+            }
+            Command::ToggleAutoIterations => {
+                let mandelbrot_state = &mut self.mandelbrot_state;
+                mandelbrot_state.auto_iterations = !mandelbrot_state.auto_iterations;
+                mandelbrot_state.auto_iterations_offset = 0;
+                log::info!("Auto iterations: {}", mandelbrot_state.auto_iterations);
+                self.request_redraw();
+            }
+            Command::Pan { dx, dy } => {
+                self.mandelbrot_state.pan_delta =
+                    accumulate_pan(self.mandelbrot_state.pan_delta, [dx, dy]);
+                self.request_redraw();
+            }
+            Command::ResetView => {
                 self.mandelbrot_state.center = [-0.5, 0.0];
                 self.mandelbrot_state.zoom = 1.0;
-                self.mandelbrot_state.needs_update = true;
-                self.window.request_redraw();
+                self.view_changed();
             }
-            KeyCode::Equal | KeyCode::NumpadAdd => {
-                // Zoom in
-                self.mandelbrot_state.zoom *= 1.5;
-                self.mandelbrot_state.needs_update = true;
-                self.window.request_redraw();
+            Command::StretchVertical(factor) => self.stretch_vertical(factor),
+            Command::ResetStretch => {
+                self.mandelbrot_state.stretch = 1.0;
+                self.view_changed();
             }
-            KeyCode::Minus | KeyCode::NumpadSubtract => {
-                // Zoom out
-                self.mandelbrot_state.zoom /= 1.5;
-                self.mandelbrot_state.needs_update = true;
-                self.window.request_redraw();
+            Command::ToggleHeatmap => {
+                self.mandelbrot_state.debug_heatmap = !self.mandelbrot_state.debug_heatmap;
+                self.view_changed();
             }
-            KeyCode::ArrowUp => {
-                // Increase iterations
-                self.mandelbrot_state.max_iterations = (self.mandelbrot_state.max_iterations + 50);
-                self.mandelbrot_state.needs_update = true;
-                self.window.request_redraw();
+            Command::ToggleSessionStats => self.toggle_session_stats(),
+            Command::CyclePresentMode => self.cycle_present_mode(),
+            Command::ToggleLowLatency => {
+                self.low_latency = !self.low_latency;
+                let capabilities = self.surface.get_capabilities(&self.adapter);
+                self.set_present_mode(preferred_present_mode(&capabilities, self.low_latency));
             }
-            KeyCode::ArrowDown => {
-                // Decrease iterations
-                self.mandelbrot_state.max_iterations =
-                    (self.mandelbrot_state.max_iterations.saturating_sub(50)).max(10);
-                self.mandelbrot_state.needs_update = true;
-                self.window.request_redraw();
+            Command::ToggleHud => {
+                self.hud.toggle(&self.window);
+                self.request_redraw();
             }
-            _ => {}
+            Command::ToggleDirtyRects => {
+                self.show_dirty_rects = !self.show_dirty_rects;
+                self.dirty_rects.clear();
+                self.request_redraw();
+            }
+            Command::ToggleSmoothColoring => {
+                self.mandelbrot_state.smooth_coloring = !self.mandelbrot_state.smooth_coloring;
+                log::info!("Smooth coloring: {}", self.mandelbrot_state.smooth_coloring);
+                self.view_changed();
+            }
+            Command::CyclePalette => self.cycle_palette(),
+            Command::SetTool(tool) => self.set_tool(tool),
+            Command::ScaleBrushRadius(factor) => {
+                self.brush.scale_radius(factor);
+                self.log_brush();
+            }
+            Command::AdjustBrushHardness(delta) => {
+                self.brush.adjust_hardness(delta);
+                self.log_brush();
+            }
+            Command::BeginStroke => self.begin_stroke(),
+            Command::ContinueStroke => self.continue_stroke(),
+            Command::EndStroke => self.end_stroke(),
+            Command::PaintDot { x, y } => {
+                self.finish_preview();
+                self.record_history();
+                let at = self.cursor_to_texel([x, y]);
+                self.paint_segment(at, at);
+            }
+            Command::Clear => {
+                // Paint lives in the fractal's texture, so a full recompute clears it
+                self.rendered_view = None;
+                self.view_changed();
+            }
+            Command::Undo => self.undo(),
+            Command::Redo => self.redo(),
+            Command::TakeSnapshot => self.take_snapshot(),
+            Command::ShowSnapshot(show) => self.show_snapshot(show),
+            Command::CopyDiagnostics => self.copy_diagnostics(),
+            Command::Screenshot => self.save_screenshot(),
+            Command::SnapToMinibrot => self.snap_to_minibrot(),
+            Command::HistoryBack => {
+                if let Some(view) = self.view_history.back() {
+                    self.restore_view(view);
+                }
+            }
+            Command::HistoryForward => {
+                if let Some(view) = self.view_history.forward() {
+                    self.restore_view(view);
+                }
+            }
+            Command::SaveBookmark(slot) => {
+                let mandelbrot_state = &self.mandelbrot_state;
+                self.bookmarks.set(
+                    slot,
+                    Bookmark {
+                        center: mandelbrot_state.center,
+                        zoom: mandelbrot_state.zoom,
+                        stretch: mandelbrot_state.stretch,
+                        max_iterations: mandelbrot_state.max_iterations,
+                    },
+                );
+                match self.bookmarks.save() {
+                    Ok(()) => log::info!("Saved bookmark {}", slot + 1),
+                    Err(e) => log::error!("Unable to save bookmarks: {:#}", e),
+                }
+            }
+            Command::GoToBookmark(slot) => match self.bookmarks.get(slot) {
+                Some(bookmark) => {
+                    self.cancel_gesture();
+                    self.mandelbrot_state.center = bookmark.center;
+                    self.mandelbrot_state.zoom = bookmark.zoom;
+                    self.mandelbrot_state.stretch = bookmark.stretch;
+                    self.mandelbrot_state.auto_iterations = false;
+                    self.mandelbrot_state.max_iterations = bookmark.max_iterations.max(1);
+                    self.view_changed();
+                }
+                None => log::info!("No bookmark {}", slot + 1),
+            },
+            Command::Export { width, height } => {
+                #[cfg(not(target_arch = "wasm32"))]
+                match self.export_image(width, height) {
+                    Ok(path) => log::info!("Exported {}x{} to {}", width, height, path.display()),
+                    Err(e) => log::error!("Unable to export: {:#}", e),
+                }
+                #[cfg(target_arch = "wasm32")]
+                log::warn!("Export isn't supported on the web ({}x{})", width, height);
+            }
+        }
+    }
+
+    /// Jumps to a view from the history.
+    fn restore_view(&mut self, view: ViewEntry) {
+        // A drag in progress would otherwise be applied on top
+        self.mandelbrot_state.pan_delta = [0.0, 0.0];
+        self.mandelbrot_state.center = view.center;
+        self.mandelbrot_state.zoom = view.zoom;
+        self.mandelbrot_state.stretch = view.stretch;
+        self.view_changed();
+    }
+
+    /// Drops any press or drag in progress, so the cursor's next motion
+    /// doesn't carry on from before a jump. A stroke is finished rather
+    /// than thrown away.
+    fn cancel_gesture(&mut self) {
+        if let Gesture::Dragging = std::mem::replace(&mut self.gesture, Gesture::Idle) {
+            self.end_drag();
+        }
+        self.mandelbrot_state.pan_delta = [0.0, 0.0];
+    }
+
+    /// Marks the fractal for recomputing and asks for a frame.
+    fn view_changed(&mut self) {
+        self.mandelbrot_state.needs_update = true;
+        self.request_redraw();
+    }
+
+    fn log_brush(&self) {
+        log::info!(
+            "Brush radius {:.1}, hardness {:.1}",
+            self.brush.radius,
+            self.brush.hardness
+        );
+    }
+
+    /// Copies the kept part of the fractal from the current canvas into the
+    /// other texture, moved by a whole number of pixels, ready for the
+    /// compute pass to fill in the exposed strips.
+    fn shift_canvas(&self, encoder: &mut wgpu::CommandEncoder, regions: &pan_reuse::ShiftRegions) {
+        let [width, height] = regions.copy_size;
+        if width == 0 || height == 0 {
+            return;
+        }
+        let extent = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texel = |texture, [x, y]: [u32; 2]| wgpu::TexelCopyTextureInfo {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d { x, y, z: 0 },
+            aspect: wgpu::TextureAspect::All,
+        };
+
+        encoder.copy_texture_to_texture(
+            texel(self.current_canvas(), regions.copy_src),
+            texel(self.next_canvas(), regions.copy_dst),
+            extent,
+        );
+    }
+
+    /// The canvas texture holding the latest frame.
+    fn current_canvas(&self) -> &wgpu::Texture {
+        if self.use_texture_a_as_input {
+            &self.canvas_texture_a
+        } else {
+            &self.canvas_texture_b
+        }
+    }
+
+    /// Opens error scopes so the matching [`State::submit`] can tell which
+    /// submission an error came from. Call before encoding.
+    fn begin_submission(&self) {
+        submit_log::open_scopes(&self.device);
+    }
+
+    /// Submits `encoder` and closes the scopes opened by `begin_submission`.
+    /// Anything they caught goes into the submit log along with what this
+    /// submission contained.
+    fn submit(
+        &self,
+        encoder: wgpu::CommandEncoder,
+        label: &'static str,
+        passes: Vec<&'static str>,
+        uniform_hashes: Vec<(&'static str, u64)>,
+    ) -> wgpu::SubmissionIndex {
+        let submission = self.queue.submit(std::iter::once(encoder.finish()));
+        self.submit_log.close_scopes(
+            &self.device,
+            self.present_stats.presents,
+            label,
+            passes,
+            uniform_hashes,
+        );
+        submission
+    }
+
+    /// The canvas texture the next compute pass writes.
+    fn next_canvas(&self) -> &wgpu::Texture {
+        if self.use_texture_a_as_input {
+            &self.canvas_texture_b
+        } else {
+            &self.canvas_texture_a
+        }
+    }
+
+    /// Re-queries the surface capabilities and, if the preferred format changed
+    /// (e.g. the window moved between an SDR and an HDR monitor), reconfigures
+    /// the surface and swaps in a display pipeline for the new format. The
+    /// canvas textures don't depend on the surface format and are left alone.
+    /// Returns whether anything changed.
+    pub fn refresh_surface_format(&mut self) -> bool {
+        let capabilities = self.surface.get_capabilities(&self.adapter);
+        let Some((format, alpha_mode)) =
+            surface_format_change(&capabilities, self.config.format, self.config.alpha_mode)
+        else {
+            return false;
+        };
+
+        log::info!(
+            "Surface changed from {:?}/{:?} to {:?}/{:?}",
+            self.config.format,
+            self.config.alpha_mode,
+            format,
+            alpha_mode
+        );
+        self.apply_surface_format(format, alpha_mode);
+        true
+    }
+
+    /// Reconfigures the surface for `format` and rebuilds everything whose
+    /// render target format depends on it.
+    pub fn apply_surface_format(
+        &mut self,
+        format: wgpu::TextureFormat,
+        alpha_mode: wgpu::CompositeAlphaMode,
+    ) {
+        self.config.format = format;
+        self.config.alpha_mode = alpha_mode;
+
+        self.render_pipeline = self
+            .render_pipelines
+            .entry(format)
+            .or_insert_with(|| {
+                create_render_pipeline(
+                    &self.device,
+                    &self.render_pipeline_layout,
+                    &self.shader_module,
+                    format,
+                    self.pipeline_cache.as_ref().map(PipelineCache::cache),
+                )
+            })
+            .clone();
+
+        if self.is_surface_configured {
+            self.surface.configure(&self.device, &self.config);
+        }
+        self.request_redraw();
+    }
+
+    /// Copies the current canvas into the snapshot texture for later comparison.
+    pub fn take_snapshot(&mut self) {
+        self.finish_preview();
+        self.begin_submission();
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Snapshot Encoder"),
+            });
+
+        encoder.copy_texture_to_texture(
+            self.current_canvas().as_image_copy(),
+            self.snapshot_texture.as_image_copy(),
+            self.current_canvas().size(),
+        );
+
+        self.submit(encoder, "snapshot", vec!["snapshot copy"], Vec::new());
+        self.snapshot_valid = true;
+        log::info!("Compare snapshot taken");
+    }
+
+    /// Switches the display between the live canvas and the snapshot.
+    /// Only the sampled texture changes; the live canvas is left alone.
+    pub fn show_snapshot(&mut self, show: bool) {
+        let show = show && self.snapshot_valid;
+        if show != self.showing_snapshot {
+            self.showing_snapshot = show;
+            // The window title says when the frozen image is up
+            self.hud.snapshot_marker = show;
+            self.window
+                .set_title(&hud::mark_snapshot(&self.window.title(), show));
+            self.request_redraw();
+        }
+    }
+
+    /// Goes back to the view and brush a PNG saved by this app was made
+    /// with. Other files are left alone, with a warning.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_settings(&mut self, path: &std::path::Path) {
+        let settings = match export::read_settings(path) {
+            Ok(Some(settings)) => settings,
+            Ok(None) => {
+                log::warn!("{} has no view settings to restore", path.display());
+                return;
+            }
+            Err(err) => {
+                log::warn!("{:#}", err);
+                return;
+            }
+        };
+        log::info!("Restoring the view from {}", path.display());
+
+        self.cancel_gesture();
+        let state = &mut self.mandelbrot_state;
+        state.pan_delta = [0.0, 0.0];
+        state.center = settings.center;
+        state.zoom = settings.zoom;
+        state.stretch = settings.stretch;
+        state.auto_iterations = false;
+        state.max_iterations = settings.max_iterations;
+        state.smooth_coloring = settings.smooth_coloring;
+        let palette = PALETTES
+            .iter()
+            .position(|palette| palette.name == settings.palette)
+            .unwrap_or_default();
+        self.set_palette(palette);
+        self.brush.color = settings.brush_color;
+        self.brush.radius = settings
+            .brush_radius
+            .clamp(MIN_BRUSH_RADIUS, MAX_BRUSH_RADIUS);
+        self.brush.hardness = settings.brush_hardness.clamp(0.0, 1.0);
+        self.view_changed();
+    }
+
+    /// Saves what's on screen to a timestamped PNG in the working directory.
+    pub fn save_screenshot(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.finish_preview();
+            let texture = if self.showing_snapshot {
+                &self.snapshot_texture
+            } else {
+                self.current_canvas()
+            };
+            let (width, height) = (texture.width(), texture.height());
+            let readback = export::Readback::new(&self.device, width, height);
+
+            self.begin_submission();
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Screenshot Encoder"),
+                });
+            readback.encode_copy(&mut encoder, texture, [0, 0]);
+            let submission = self.submit(encoder, "screenshot", vec!["readback copy"], Vec::new());
+
+            let path = export::timestamped_path("screenshot", "png");
+            let saved = readback
+                .read(&self.device, submission)
+                .and_then(|mut pixels| {
+                    export::to_screen_colors(&mut pixels);
+                    export::save_png(&path, width, height, &pixels, &self.view_settings())
+                });
+            match saved {
+                Ok(()) => log::info!("Screenshot saved to {}", path.display()),
+                Err(e) => log::error!("Unable to save screenshot: {:#}", e),
+            }
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        log::warn!("Screenshots aren't supported on the web");
+    }
+
+    /// Renders the current view at `width` x `height` and saves it as a
+    /// timestamped PNG, independent of the window size. The image is
+    /// computed a strip of rows at a time, in tiles no larger than the
+    /// device allows, and streamed into the file, so memory use stays at a
+    /// couple of strips however large the image is. The vertical extent
+    /// matches the screen, so an export with the window's aspect ratio
+    /// frames exactly what's on screen.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn export_image(&mut self, width: u32, height: u32) -> anyhow::Result<std::path::PathBuf> {
+        anyhow::ensure!(width > 0 && height > 0, "empty export size");
+
+        // Finish any pending drag first, so the export matches the screen
+        let aspect_ratio = self.aspect_ratio();
+        self.mandelbrot_state.apply_pan(aspect_ratio);
+
+        let max_size = self.device.limits().max_texture_dimension_2d;
+        let tile_size = EXPORT_TILE_SIZE.min(max_size);
+        let strip_height = EXPORT_STRIP_HEIGHT.min(max_size).min(height);
+        let tile_texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Export Tile Texture"),
+            size: wgpu::Extent3d {
+                width: tile_size.min(width),
+                height: strip_height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: RenderPath::target_usages(self.render_path.is_compute())
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let tile_view = tile_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let export_params_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Export Params Buffer"),
+            size: std::mem::size_of::<MandelbrotParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = match &self.render_path {
+            RenderPath::Compute(compute) => compute.bind_target(
+                &self.device,
+                "Export Compute Bind Group",
+                &tile_view,
+                &export_params_buffer,
+                &self.palette_buffer,
+            ),
+            RenderPath::Fragment(fragment) => {
+                fragment.bind_params(&self.device, &export_params_buffer, &self.palette_buffer)
+            }
+        };
+
+        let path = export::timestamped_path("export", "png");
+        let mut stream = export::PngStream::create(&path, width, height, &self.view_settings())?;
+        let write_strip = |stream: &mut export::PngStream, strip: export::Strip| {
+            let mut pixels = strip.read(&self.device, width)?;
+            export::to_screen_colors(&mut pixels);
+            stream.write_rows(&pixels)
+        };
+
+        // Each strip is encoded while the GPU computes the next, so at most
+        // two strips of readback buffers exist at once
+        let mut in_flight = None;
+        for strip_y in (0..height).step_by(strip_height as usize) {
+            let rows = strip_height.min(height - strip_y);
+            let mut strip = export::Strip::new(rows);
+            for tile_x in (0..width).step_by(tile_size as usize) {
+                let tile_width = tile_size.min(width - tile_x);
+
+                let mandelbrot_state = &self.mandelbrot_state;
+                let params = MandelbrotParams {
+                    center: mandelbrot_state.center,
+                    zoom: mandelbrot_state.zoom,
+                    max_iterations: mandelbrot_state.max_iterations,
+                    debug_heatmap: mandelbrot_state.debug_heatmap as u32,
+                    stretch: mandelbrot_state.stretch,
+                    origin: [0, 0],
+                    // Nothing to reuse
+                    reuse_min: [0, 0],
+                    reuse_max: [0, 0],
+                    smooth_coloring: mandelbrot_state.smooth_coloring as u32,
+                    _padding0: 0,
+                    tile_offset: [tile_x, strip_y],
+                    image_size: [width, height],
+                    _padding1: [0; 2],
+                };
+                self.queue
+                    .write_buffer(&export_params_buffer, 0, bytemuck::cast_slice(&[params]));
+
+                let readback = export::Readback::new(&self.device, tile_width, rows);
+                self.begin_submission();
+                let mut encoder =
+                    self.device
+                        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                            label: Some("Export Encoder"),
+                        });
+                match &self.render_path {
+                    RenderPath::Compute(compute) => {
+                        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                            label: Some("Export Compute Pass"),
+                            timestamp_writes: None,
+                        });
+                        compute_pass.set_pipeline(&compute.pipeline);
+                        compute_pass.set_bind_group(0, &bind_group, &[]);
+                        compute_pass.dispatch_workgroups(
+                            tile_width.div_ceil(WORKGROUP_SIZE),
+                            rows.div_ceil(WORKGROUP_SIZE),
+                            1,
+                        );
+                    }
+                    RenderPath::Fragment(fragment) => fragment.encode(
+                        &mut encoder,
+                        &tile_texture,
+                        &bind_group,
+                        [0, 0, tile_width, rows],
+                        None,
+                    ),
+                }
+                readback.encode_copy(&mut encoder, &tile_texture, [0, 0]);
+                let submission = self.submit(
+                    encoder,
+                    "export",
+                    vec!["mandelbrot tile", "readback copy"],
+                    vec![(
+                        "params",
+                        submit_log::hash_uniform(bytemuck::bytes_of(&params)),
+                    )],
+                );
+                strip.push(tile_x, readback, submission);
+            }
+
+            if let Some(previous) = in_flight.replace(strip) {
+                write_strip(&mut stream, previous)?;
+                log::info!("Export {}% done", strip_y as u64 * 100 / height as u64);
+            }
+        }
+        if let Some(last) = in_flight {
+            write_strip(&mut stream, last)?;
+        }
+        stream.finish()?;
+        Ok(path)
+    }
+
+    /// The settings saved alongside screenshots and exports.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn view_settings(&self) -> export::ViewSettings {
+        let state = &self.mandelbrot_state;
+        export::ViewSettings {
+            center: state.center,
+            zoom: state.zoom,
+            stretch: state.stretch,
+            max_iterations: state.max_iterations,
+            palette: PALETTES[state.palette].name,
+            smooth_coloring: state.smooth_coloring,
+            brush_color: self.brush.color,
+            brush_radius: self.brush.radius,
+            brush_hardness: self.brush.hardness,
+        }
+    }
+
+    /// Puts a present statistics report on the clipboard (or in the log on web).
+    pub fn copy_diagnostics(&self) {
+        let mut report = self.present_stats.report(
+            &self.adapter.get_info(),
+            &self.device.limits(),
+            &self.config,
+        );
+        self.submit_log.write_report(&mut report);
+        report.push_str(&format!("Render path: {}\n", self.render_path.name()));
+        report.push_str(&match &self.pipeline_cache {
+            Some(cache) => cache.describe(),
+            None => "Pipeline cache: unsupported\n".to_string(),
+        });
+        #[cfg(not(target_arch = "wasm32"))]
+        report.push_str(&match &self.session_stats {
+            Some(stats) => format!("Session stats: {}\n", stats.path().display()),
+            None => "Session stats: off\n".to_string(),
+        });
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            match arboard::Clipboard::new()
+                .and_then(|mut clipboard| clipboard.set_text(report.clone()))
+            {
+                Ok(()) => log::info!("Diagnostics copied to clipboard"),
+                Err(e) => log::warn!(
+                    "Unable to copy diagnostics to clipboard ({}):\n{}",
+                    e,
+                    report
+                ),
+            }
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        log::info!("Diagnostics:\n{}", report);
+    }
+
+    pub fn set_tool(&mut self, tool: Tool) {
+        if tool != Tool::Pan && !self.render_path.is_compute() {
+            log::warn!("Painting needs compute shaders, which this GPU doesn't have");
+            return;
+        }
+        if tool != self.tool {
+            self.end_stroke();
+            self.mandelbrot_state.dragging = false;
+            self.gesture = Gesture::Idle;
+            self.tool = tool;
+            log::info!("Tool: {:?}", tool);
+        }
+    }
+
+    /// Converts a normalized cursor position to canvas texel coordinates.
+    fn cursor_to_texel(&self, cursor: [f64; 2]) -> [f32; 2] {
+        [
+            cursor[0] as f32 * self.current_canvas().width() as f32,
+            cursor[1] as f32 * self.current_canvas().height() as f32,
+        ]
+    }
+
+    /// Starts a brush stroke with a dab under the cursor.
+    pub fn begin_stroke(&mut self) {
+        self.begin_stroke_from(self.mandelbrot_state.cursor_location);
+    }
+
+    /// Starts a brush stroke at `start`, a normalized screen position, and
+    /// draws it on to the cursor.
+    fn begin_stroke_from(&mut self, start: [f64; 2]) {
+        self.painting = true;
+        self.finish_preview();
+        self.record_history();
+        let from = self.cursor_to_texel(start);
+        let to = self.cursor_to_texel(self.mandelbrot_state.cursor_location);
+        self.paint_segment(from, to);
+    }
+
+    /// Left button down: starts a gesture that is a click until the cursor
+    /// moves far enough or the button is held long enough.
+    pub fn pointer_pressed(&mut self) {
+        self.step_gesture(PointerEvent::Press {
+            at: self.mandelbrot_state.cursor_location,
+            now: web_time::Instant::now(),
+        });
+    }
+
+    /// Cursor motion, after `cursor_location` has been updated.
+    pub fn pointer_moved(&mut self) {
+        self.step_gesture(PointerEvent::Move {
+            to: self.mandelbrot_state.cursor_location,
+            now: web_time::Instant::now(),
+        });
+    }
+
+    /// Left button up: ends a drag, or resolves a pending gesture as a click.
+    pub fn pointer_released(&mut self) {
+        self.step_gesture(PointerEvent::Release);
+    }
+
+    /// Feeds `event` to the gesture state machine and carries out what it
+    /// decides with the current tool.
+    fn step_gesture(&mut self, event: PointerEvent) {
+        let size = self.window.inner_size();
+        let threshold = DRAG_THRESHOLD * self.window.scale_factor();
+        let (gesture, action) = gesture_transition(
+            self.gesture,
+            event,
+            [size.width as f64, size.height as f64],
+            threshold,
+        );
+        self.gesture = gesture;
+        match action {
+            GestureAction::None => {}
+            GestureAction::StartDrag { start } => self.start_drag(start),
+            GestureAction::ContinueDrag => {
+                if self.painting {
+                    self.apply_command(Command::ContinueStroke);
+                }
+                // Queue the motion; update() applies it once per frame
+                if self.mandelbrot_state.dragging {
+                    let from = self.mandelbrot_state.prev_cursor_location;
+                    let to = self.mandelbrot_state.cursor_location;
+                    self.apply_command(Command::Pan {
+                        dx: to[0] - from[0],
+                        dy: to[1] - from[1],
+                    });
+                }
+            }
+            GestureAction::EndDrag => self.end_drag(),
+            GestureAction::Click { at } => match self.tool {
+                Tool::Pan => {}
+                // A click leaves a single dab where it was pressed
+                Tool::Brush | Tool::Eraser => {
+                    self.apply_command(Command::PaintDot { x: at[0], y: at[1] })
+                }
+            },
+        }
+    }
+
+    /// Starts a drag from the press at `start`, replaying the motion since
+    /// so it isn't lost to the threshold.
+    fn start_drag(&mut self, start: [f64; 2]) {
+        match self.tool {
+            Tool::Pan => {
+                self.mandelbrot_state.dragging = true;
+                let to = self.mandelbrot_state.cursor_location;
+                self.apply_command(Command::Pan {
+                    dx: to[0] - start[0],
+                    dy: to[1] - start[1],
+                });
+            }
+            Tool::Brush | Tool::Eraser => self.begin_stroke_from(start),
+        }
+    }
+
+    fn end_drag(&mut self) {
+        match self.tool {
+            Tool::Pan => self.mandelbrot_state.dragging = false,
+            Tool::Brush | Tool::Eraser => self.apply_command(Command::EndStroke),
+        }
+    }
+
+    /// Remembers the canvas from before a stroke for undo.
+    fn record_history(&mut self) {
+        self.begin_submission();
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("History Encoder"),
+            });
+        let canvas = if self.use_texture_a_as_input {
+            &self.canvas_texture_a
+        } else {
+            &self.canvas_texture_b
+        };
+        self.history
+            .record(&self.device, &mut encoder, canvas, self.rendered_view);
+        self.submit(encoder, "history record", vec!["history copy"], Vec::new());
+    }
+
+    /// Extends the active stroke from the previous cursor position to the current one.
+    pub fn continue_stroke(&mut self) {
+        if !self.painting {
+            return;
+        }
+        let from = self.cursor_to_texel(self.mandelbrot_state.prev_cursor_location);
+        let to = self.cursor_to_texel(self.mandelbrot_state.cursor_location);
+        self.paint_segment(from, to);
+    }
+
+    pub fn end_stroke(&mut self) {
+        self.painting = false;
+    }
+
+    /// Reverts the canvas to before the last stroke.
+    pub fn undo(&mut self) {
+        self.step_history(CanvasHistory::undo, "Undo");
+    }
+
+    /// Reapplies the last undone stroke.
+    pub fn redo(&mut self) {
+        self.step_history(CanvasHistory::redo, "Redo");
+    }
+
+    fn step_history(
+        &mut self,
+        step: fn(
+            &mut CanvasHistory,
+            &wgpu::Device,
+            &mut wgpu::CommandEncoder,
+            &wgpu::Texture,
+            Option<RenderedView>,
+        ) -> Option<Restored>,
+        name: &str,
+    ) {
+        self.end_stroke();
+        // The canvas should match a view before it's saved for the way back
+        self.finish_preview();
+        self.begin_submission();
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("History Encoder"),
+            });
+        let canvas = if self.use_texture_a_as_input {
+            &self.canvas_texture_a
+        } else {
+            &self.canvas_texture_b
+        };
+        let restored = step(
+            &mut self.history,
+            &self.device,
+            &mut encoder,
+            canvas,
+            self.rendered_view,
+        );
+        // Submitted even if empty, to close the error scopes
+        self.submit(encoder, "history step", vec!["history copies"], Vec::new());
+        let Some(restored) = restored else {
+            log::info!("Nothing to {}", name.to_lowercase());
+            return;
+        };
+        log::info!("{}", name);
+        self.rendered_view = restored.view;
+        if let Some(view) = restored.view {
+            // Go back to where the restored canvas was rendered rather than
+            // recomputing over it
+            self.mandelbrot_state.show_view(&view);
+            self.set_palette(view.palette);
+        }
+        self.request_redraw();
+    }
+
+    /// Paints a round-capped line from `from` to `to` (texel coordinates) into
+    /// the canvas, dispatching only over the segment's bounding box.
+    fn paint_segment(&mut self, from: [f32; 2], to: [f32; 2]) {
+        // Bands still to come write the other texture, which the splat below
+        // borrows, and would land over the paint when the textures flip
+        self.finish_preview();
+        let RenderPath::Compute(compute) = &self.render_path else {
+            return;
+        };
+        let radius = self.brush.radius;
+        let size = [
+            self.current_canvas().width(),
+            self.current_canvas().height(),
+        ];
+        let mut min = [0u32; 2];
+        let mut max = [0u32; 2];
+        for axis in 0..2 {
+            let lo = from[axis].min(to[axis]) - radius;
+            let hi = from[axis].max(to[axis]) + radius;
+            min[axis] = lo.floor().clamp(0.0, size[axis] as f32) as u32;
+            max[axis] = hi.ceil().clamp(0.0, size[axis] as f32) as u32;
+        }
+        if min[0] >= max[0] || min[1] >= max[1] {
+            return;
+        }
+        if self.show_dirty_rects {
+            if self.dirty_rects.len() == DIRTY_RECT_TRAIL {
+                self.dirty_rects.pop_front();
+            }
+            self.dirty_rects.push_back([min[0], min[1], max[0], max[1]]);
+        }
+
+        let params = BrushParams {
+            color: self.brush.color,
+            p0: from,
+            p1: to,
+            origin: min,
+            radius,
+            hardness: self.brush.hardness,
+            erase: (self.tool == Tool::Eraser) as u32,
+            _padding: [0; 3],
+        };
+        self.queue.write_buffer(
+            &self.brush_params_buffer,
+            0,
+            bytemuck::cast_slice(&[params]),
+        );
+
+        self.begin_submission();
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Brush Encoder"),
+            });
+
+        // The splat blends over what's there, so give it a copy to read
+        let region = wgpu::Extent3d {
+            width: max[0] - min[0],
+            height: max[1] - min[1],
+            depth_or_array_layers: 1,
+        };
+        let texel = |texture| wgpu::TexelCopyTextureInfo {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d {
+                x: min[0],
+                y: min[1],
+                z: 0,
+            },
+            aspect: wgpu::TextureAspect::All,
+        };
+        encoder.copy_texture_to_texture(
+            texel(self.current_canvas()),
+            texel(self.next_canvas()),
+            region,
+        );
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Brush Compute Pass"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&compute.brush_pipeline);
+        // Paint over the latest frame in place, reading the copy in the other
+        // texture, which is free again once the splat is done
+        let brush_bind_group = if self.use_texture_a_as_input {
+            &compute.bind_groups.brush_a
+        } else {
+            &compute.bind_groups.brush_b
+        };
+        compute_pass.set_bind_group(0, brush_bind_group, &[]);
+
+        let workgroup_size = 8;
+        compute_pass.dispatch_workgroups(
+            (max[0] - min[0]).div_ceil(workgroup_size),
+            (max[1] - min[1]).div_ceil(workgroup_size),
+            1,
+        );
+        drop(compute_pass);
+
+        // Submit per segment so each dispatch sees its own params
+        self.submit(
+            encoder,
+            "brush",
+            vec!["brush copy", "splat"],
+            vec![(
+                "brush params",
+                submit_log::hash_uniform(bytemuck::bytes_of(&params)),
+            )],
+        );
+        self.request_redraw();
+    }
+
+    /// Switches to the next built-in palette and recolors the fractal.
+    pub fn cycle_palette(&mut self) {
+        let index = (self.mandelbrot_state.palette + 1) % PALETTES.len();
+        self.set_palette(index);
+        log::info!("Palette: {}", PALETTES[index].name);
+        self.view_changed();
+    }
+
+    /// Makes `index` the current palette, in the state and in the uniform
+    /// the fractal passes color with. Doesn't recompute anything.
+    fn set_palette(&mut self, index: usize) {
+        self.mandelbrot_state.palette = index;
+        write_palette(&self.queue, &self.palette_buffer, index);
+    }
+
+    /// Width over height of the canvas, as used by the fractal mapping.
+    pub fn aspect_ratio(&self) -> f32 {
+        self.config.width.max(1) as f32 / self.config.height.max(1) as f32
+    }
+
+    /// Centers the view on the nearest minibrot within sight and zooms so
+    /// it fills the window.
+    pub fn snap_to_minibrot(&mut self) {
+        self.mandelbrot_state.apply_pan(self.aspect_ratio());
+        let state = &self.mandelbrot_state;
+        let seed = [state.center[0] as f64, state.center[1] as f64];
+        // Half the larger side of the view, in the complex plane
+        let radius =
+            (self.aspect_ratio() as f64).max(1.0 / state.stretch as f64) / state.zoom as f64;
+
+        let Some(minibrot) = minibrot::find_nearby(seed, radius, state.max_iterations) else {
+            log::warn!("No minibrot found in view; try more iterations or zooming out");
+            return;
+        };
+        log::info!(
+            "Snapped to period {} minibrot at ({}, {}), size {:.3e}",
+            minibrot.period,
+            minibrot.center[0],
+            minibrot.center[1],
+            minibrot.size
+        );
+        self.mandelbrot_state.center = [minibrot.center[0] as f32, minibrot.center[1] as f32];
+        self.mandelbrot_state.zoom = (MINIBROT_FRAMING / minibrot.size) as f32;
+        self.view_changed();
+    }
+
+    /// Starts recording every fractal render to a CSV file, or stops and
+    /// writes it out.
+    pub fn toggle_session_stats(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        match self.session_stats.take() {
+            // Dropping writes the file
+            Some(_) => {}
+            None => {
+                let stats = SessionStats::new(export::timestamped_path("session", "csv"));
+                log::info!("Recording session stats to {}", stats.path().display());
+                self.session_stats = Some(stats);
+            }
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        log::warn!("Session stats need a filesystem and aren't available on the web");
+    }
+
+    /// Switches to the next present mode the surface supports.
+    pub fn cycle_present_mode(&mut self) {
+        let modes = self.surface.get_capabilities(&self.adapter).present_modes;
+        if modes.is_empty() {
+            return;
+        }
+        let next = modes
+            .iter()
+            .position(|&mode| mode == self.config.present_mode)
+            .map_or(0, |i| (i + 1) % modes.len());
+        self.set_present_mode(modes[next]);
+    }
+
+    /// Reconfigures the surface between frames, so no frame is holding a
+    /// surface texture and nothing goes through the Outdated recovery.
+    fn set_present_mode(&mut self, mode: wgpu::PresentMode) {
+        if mode == self.config.present_mode {
+            return;
+        }
+        self.config.present_mode = mode;
+        if self.is_surface_configured {
+            self.surface.configure(&self.device, &self.config);
+        }
+        log::info!("Present mode: {:?}", mode);
+        self.request_redraw();
+    }
+
+    /// GPU time of the passes in the latest frame whose timestamps have been
+    /// read back, or `None` if the device can't measure it.
+    pub fn gpu_timings(&self) -> Option<GpuTimings> {
+        self.gpu_timer.as_ref().and_then(GpuTimer::latest)
+    }
+
+    /// Scales only the vertical axis of the view.
+    pub fn stretch_vertical(&mut self, factor: f32) {
+        if !self.mandelbrot_state.stretch_by(factor) {
+            return;
+        }
+        log::info!("Aspect stretch 1:{:.3}", self.mandelbrot_state.stretch);
+        self.view_changed();
+    }
+
+    /// Asks the window for a frame unless one is already pending or
+    /// nothing is visible.
+    pub fn request_redraw(&mut self) {
+        if self.visible && !self.redraw_requested {
+            self.redraw_requested = true;
+            self.window.request_redraw();
+        }
+    }
+
+    /// Pauses rendering while hidden and kicks it off again when shown.
+    pub fn set_visible(&mut self, visible: bool) {
+        if visible != self.visible {
+            self.visible = visible;
+            log::info!("Canvas visible: {}", visible);
+            self.request_redraw();
         }
     }
 
     pub fn update(&mut self) {
-        // Any per-frame updates can go here
+        // Apply the drag accumulated since the last frame
+        let aspect_ratio = self.aspect_ratio();
+        self.mandelbrot_state.apply_pan(aspect_ratio);
+
+        // Only a changed limit needs a recompute
+        let mandelbrot_state = &mut self.mandelbrot_state;
+        if mandelbrot_state.auto_iterations {
+            let max_iterations = auto_iterations(
+                mandelbrot_state.zoom,
+                mandelbrot_state.auto_iterations_offset,
+                mandelbrot_state.auto_iterations_ceiling,
+            );
+            if max_iterations != mandelbrot_state.max_iterations {
+                mandelbrot_state.max_iterations = max_iterations;
+                mandelbrot_state.needs_update = true;
+            }
+        }
+
+        let gpu_timings = self.gpu_timings();
+        #[cfg(not(target_arch = "wasm32"))]
+        if let (
+            Some(stats),
+            Some(GpuTimings {
+                frame,
+                compute: Some(compute),
+                ..
+            }),
+        ) = (&mut self.session_stats, gpu_timings)
+        {
+            stats.attach_timing(frame, compute);
+        }
+        self.hud.update_title(
+            &self.window,
+            &self.present_stats,
+            gpu_timings,
+            self.config.present_mode,
+            self.mandelbrot_state.zoom,
+            self.mandelbrot_state.max_iterations,
+        );
+
+        self.view_history.observe(
+            ViewEntry {
+                center: self.mandelbrot_state.center,
+                zoom: self.mandelbrot_state.zoom,
+                stretch: self.mandelbrot_state.stretch,
+            },
+            web_time::Instant::now(),
+        );
+    }
+}
+
+/// What the display pass shows.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum DisplaySource {
+    /// The compare snapshot.
+    Snapshot,
+    /// The low-resolution preview.
+    Preview,
+    /// The canvas a banded render is filling in.
+    BandTarget,
+    /// The canvas texture holding the latest frame, A or B.
+    Canvas { a: bool },
+}
+
+/// Picks what to show: a held compare key wins over everything, then a
+/// preview, then a banded render in progress, then the latest canvas.
+/// Only the snapshot flag depends on the compare key, so showing the
+/// snapshot and letting go returns to exactly what was shown before.
+fn display_source(
+    showing_snapshot: bool,
+    showing_preview: bool,
+    banding: bool,
+    latest_is_a: bool,
+) -> DisplaySource {
+    if showing_snapshot {
+        DisplaySource::Snapshot
+    } else if showing_preview {
+        DisplaySource::Preview
+    } else if banding {
+        DisplaySource::BandTarget
+    } else {
+        DisplaySource::Canvas { a: latest_is_a }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(not(target_arch = "wasm32"))]
+    use crate::test_gpu;
+
+    #[test]
+    fn compare_snapshot_only_changes_what_is_shown() {
+        for preview in [false, true] {
+            for banding in [false, true] {
+                for latest_is_a in [false, true] {
+                    let live = display_source(false, preview, banding, latest_is_a);
+                    assert_ne!(live, DisplaySource::Snapshot);
+                    assert_eq!(
+                        display_source(true, preview, banding, latest_is_a),
+                        DisplaySource::Snapshot
+                    );
+                    // Letting go of the compare key shows the live image again
+                    assert_eq!(display_source(false, preview, banding, latest_is_a), live);
+                }
+            }
+        }
+    }
+
+    fn capabilities(
+        formats: &[wgpu::TextureFormat],
+        alpha_modes: &[wgpu::CompositeAlphaMode],
+    ) -> wgpu::SurfaceCapabilities {
+        wgpu::SurfaceCapabilities {
+            formats: formats.to_vec(),
+            present_modes: vec![wgpu::PresentMode::Fifo],
+            alpha_modes: alpha_modes.to_vec(),
+            usages: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        }
+    }
+
+    #[test]
+    fn surface_format_prefers_srgb() {
+        use wgpu::TextureFormat::*;
+        let opaque = [wgpu::CompositeAlphaMode::Opaque];
+        assert_eq!(
+            preferred_surface_format(&capabilities(&[Bgra8Unorm, Bgra8UnormSrgb], &opaque)),
+            Bgra8UnormSrgb
+        );
+        // An HDR surface listing a linear format first
+        assert_eq!(
+            preferred_surface_format(&capabilities(
+                &[Rgba16Float, Rgb10a2Unorm, Rgba8UnormSrgb],
+                &opaque
+            )),
+            Rgba8UnormSrgb
+        );
+        // Without an sRGB format the surface's first choice is taken
+        assert_eq!(
+            preferred_surface_format(&capabilities(&[Rgba16Float, Bgra8Unorm], &opaque)),
+            Rgba16Float
+        );
+    }
+
+    #[test]
+    fn surface_changes_only_when_the_capabilities_call_for_it() {
+        use wgpu::CompositeAlphaMode::*;
+        use wgpu::TextureFormat::*;
+        let sdr = capabilities(&[Bgra8UnormSrgb, Bgra8Unorm], &[Opaque, PreMultiplied]);
+        let hdr = capabilities(&[Rgba16Float, Rgb10a2Unorm], &[PreMultiplied]);
+
+        assert_eq!(surface_format_change(&sdr, Bgra8UnormSrgb, Opaque), None);
+        assert_eq!(
+            surface_format_change(&sdr, Bgra8UnormSrgb, PreMultiplied),
+            None
+        );
+        // Moving to the other monitor and back
+        assert_eq!(
+            surface_format_change(&hdr, Bgra8UnormSrgb, Opaque),
+            Some((Rgba16Float, PreMultiplied))
+        );
+        assert_eq!(
+            surface_format_change(&sdr, Rgba16Float, PreMultiplied),
+            Some((Bgra8UnormSrgb, PreMultiplied))
+        );
+        // A format the surface can still take isn't kept if it isn't the
+        // preferred one
+        assert_eq!(
+            surface_format_change(&sdr, Bgra8Unorm, Opaque),
+            Some((Bgra8UnormSrgb, Opaque))
+        );
+        // Minimized surfaces may report nothing
+        assert_eq!(
+            surface_format_change(&capabilities(&[], &[]), Bgra8UnormSrgb, Opaque),
+            None
+        );
+    }
+
+    #[test]
+    fn present_mode_is_fifo_unless_mailbox_is_wanted_and_there() {
+        use wgpu::PresentMode::*;
+        let mut with_mailbox = capabilities(&[wgpu::TextureFormat::Bgra8UnormSrgb], &[]);
+        with_mailbox.present_modes = vec![Fifo, Mailbox, Immediate];
+        let mut without_mailbox = capabilities(&[wgpu::TextureFormat::Bgra8UnormSrgb], &[]);
+        without_mailbox.present_modes = vec![Fifo, Immediate];
+
+        assert_eq!(preferred_present_mode(&with_mailbox, false), Fifo);
+        assert_eq!(preferred_present_mode(&with_mailbox, true), Mailbox);
+        assert_eq!(preferred_present_mode(&without_mailbox, true), Fifo);
+    }
+
+    #[test]
+    fn display_prefers_preview_then_bands_then_canvas() {
+        assert_eq!(
+            display_source(false, true, true, true),
+            DisplaySource::Preview
+        );
+        assert_eq!(
+            display_source(false, false, true, true),
+            DisplaySource::BandTarget
+        );
+        assert_eq!(
+            display_source(false, false, false, false),
+            DisplaySource::Canvas { a: false }
+        );
+    }
+
+    #[test]
+    fn sizes_within_the_texture_limit_are_kept() {
+        assert_eq!(clamp_to_texture_limit(1920, 1080, 8192), (1920, 1080));
+        assert_eq!(clamp_to_texture_limit(8192, 8192, 8192), (8192, 8192));
+        assert_eq!(clamp_to_texture_limit(0, 600, 8192), (0, 600));
+    }
+
+    #[test]
+    fn one_side_over_the_limit_keeps_the_aspect_ratio() {
+        assert_eq!(clamp_to_texture_limit(16384, 4096, 8192), (8192, 2048));
+        assert_eq!(clamp_to_texture_limit(1000, 4000, 2048), (512, 2048));
+    }
+
+    #[test]
+    fn both_sides_over_the_limit_fit_the_longer_one() {
+        assert_eq!(clamp_to_texture_limit(20000, 10000, 8192), (8192, 4096));
+        assert_eq!(
+            clamp_to_texture_limit(u32::MAX, u32::MAX, 8192),
+            (8192, 8192)
+        );
+    }
+
+    #[test]
+    fn auto_iterations_grow_with_zoom() {
+        assert_eq!(auto_iterations(1.0, 0, 10_000), 100);
+        assert_eq!(auto_iterations(2.0, 0, 10_000), 150);
+        assert_eq!(auto_iterations(1024.0, 0, 10_000), 600);
+        let mut previous = 0;
+        for octave in 0..40 {
+            let iterations = auto_iterations(2f32.powi(octave), 0, 10_000);
+            assert!(iterations > previous);
+            previous = iterations;
+        }
+        // Zooming out past the whole set doesn't go below the zoom 1 value
+        assert_eq!(auto_iterations(0.25, 0, 10_000), 100);
+    }
+
+    #[test]
+    fn auto_iterations_offset_shifts_the_value() {
+        assert_eq!(auto_iterations(1024.0, 250, 10_000), 850);
+        assert_eq!(auto_iterations(1024.0, -250, 10_000), 350);
+        // but never below the minimum
+        assert_eq!(auto_iterations(1.0, -1000, 10_000), MIN_ITERATIONS);
+    }
+
+    #[test]
+    fn auto_iterations_stop_at_the_ceiling() {
+        assert_eq!(auto_iterations(1e30, 0, 2000), 2000);
+        assert_eq!(auto_iterations(1024.0, 5000, 2000), 2000);
+        // A ceiling under the minimum still leaves the minimum
+        assert_eq!(auto_iterations(1024.0, 0, 0), MIN_ITERATIONS);
+    }
+
+    #[test]
+    fn gesture_transitions() {
+        let pressed_at = web_time::Instant::now();
+        let start = [0.5, 0.5];
+        let pending = Gesture::Pending { start, pressed_at };
+        // 800x600 window with a 4 pixel threshold
+        let step = |gesture, event| gesture_transition(gesture, event, [800.0, 600.0], 4.0);
+        let press = PointerEvent::Press {
+            at: [0.25, 0.25],
+            now: pressed_at,
+        };
+        let new_press = Gesture::Pending {
+            start: [0.25, 0.25],
+            pressed_at,
+        };
+        // 2 pixels right: a wobble
+        let wobble = PointerEvent::Move {
+            to: [0.5 + 2.0 / 800.0, 0.5],
+            now: pressed_at,
+        };
+        // 10 pixels down: past the threshold
+        let far = PointerEvent::Move {
+            to: [0.5, 0.5 + 10.0 / 600.0],
+            now: pressed_at,
+        };
+        // The same wobble once the button has been held long enough
+        let held = PointerEvent::Move {
+            to: [0.5 + 2.0 / 800.0, 0.5],
+            now: pressed_at + DRAG_HOLD_TIMEOUT,
+        };
+        let release = PointerEvent::Release;
+
+        let cases = [
+            (Gesture::Idle, press, new_press, GestureAction::None),
+            (Gesture::Idle, wobble, Gesture::Idle, GestureAction::None),
+            (Gesture::Idle, far, Gesture::Idle, GestureAction::None),
+            (Gesture::Idle, held, Gesture::Idle, GestureAction::None),
+            (Gesture::Idle, release, Gesture::Idle, GestureAction::None),
+            (pending, press, new_press, GestureAction::None),
+            (pending, wobble, pending, GestureAction::None),
+            (
+                pending,
+                far,
+                Gesture::Dragging,
+                GestureAction::StartDrag { start },
+            ),
+            (
+                pending,
+                held,
+                Gesture::Dragging,
+                GestureAction::StartDrag { start },
+            ),
+            (
+                pending,
+                release,
+                Gesture::Idle,
+                GestureAction::Click { at: start },
+            ),
+            (Gesture::Dragging, press, new_press, GestureAction::EndDrag),
+            (
+                Gesture::Dragging,
+                wobble,
+                Gesture::Dragging,
+                GestureAction::ContinueDrag,
+            ),
+            (
+                Gesture::Dragging,
+                far,
+                Gesture::Dragging,
+                GestureAction::ContinueDrag,
+            ),
+            (
+                Gesture::Dragging,
+                held,
+                Gesture::Dragging,
+                GestureAction::ContinueDrag,
+            ),
+            (
+                Gesture::Dragging,
+                release,
+                Gesture::Idle,
+                GestureAction::EndDrag,
+            ),
+        ];
+        for (gesture, event, next, action) in cases {
+            assert_eq!(
+                step(gesture, event),
+                (next, action),
+                "{:?} on {:?}",
+                event,
+                gesture
+            );
+        }
+    }
+
+    #[test]
+    fn press_move_release_orderings() {
+        let pressed_at = web_time::Instant::now();
+        let run = |events: &[PointerEvent]| {
+            let mut gesture = Gesture::Idle;
+            let mut actions = Vec::new();
+            for &event in events {
+                let (next, action) = gesture_transition(gesture, event, [800.0, 600.0], 4.0);
+                gesture = next;
+                if action != GestureAction::None {
+                    actions.push(action);
+                }
+            }
+            (gesture, actions)
+        };
+        let press = PointerEvent::Press {
+            at: [0.5, 0.5],
+            now: pressed_at,
+        };
+        let moved = |x: f64| PointerEvent::Move {
+            to: [x, 0.5],
+            now: pressed_at,
+        };
+
+        // Press and release in place: a click
+        assert_eq!(
+            run(&[press, PointerEvent::Release]),
+            (Gesture::Idle, vec![GestureAction::Click { at: [0.5, 0.5] }])
+        );
+        // Wobbles under the threshold are still a click at the press
+        assert_eq!(
+            run(&[press, moved(0.501), moved(0.499), PointerEvent::Release]),
+            (Gesture::Idle, vec![GestureAction::Click { at: [0.5, 0.5] }])
+        );
+        // Crossing the threshold starts the drag from the press position,
+        // then each move continues it until the release
+        assert_eq!(
+            run(&[
+                press,
+                moved(0.501),
+                moved(0.6),
+                moved(0.7),
+                PointerEvent::Release
+            ]),
+            (
+                Gesture::Idle,
+                vec![
+                    GestureAction::StartDrag { start: [0.5, 0.5] },
+                    GestureAction::ContinueDrag,
+                    GestureAction::EndDrag,
+                ]
+            )
+        );
+        // Motion before any press does nothing
+        assert_eq!(
+            run(&[moved(0.7), PointerEvent::Release]),
+            (Gesture::Idle, vec![])
+        );
+    }
+
+    #[test]
+    fn small_pans_add_up_to_one_big_pan() {
+        // A tenth of a pixel of an 800 pixel wide window at a time
+        let step = [0.1 / 800.0, -0.05 / 600.0];
+        let mut pan_delta = [0.0; 2];
+        for _ in 0..1000 {
+            pan_delta = accumulate_pan(pan_delta, step);
+        }
+        let one_move = accumulate_pan([0.0; 2], [100.0 / 800.0, -50.0 / 600.0]);
+        for axis in 0..2 {
+            assert!((pan_delta[axis] - one_move[axis]).abs() < 1e-12);
+        }
+
+        // and move the view the same
+        let aspect_ratio = 800.0 / 600.0;
+        let mut small = MandelbrotState {
+            pan_delta,
+            ..Default::default()
+        };
+        let mut big = MandelbrotState {
+            pan_delta: one_move,
+            ..Default::default()
+        };
+        small.apply_pan(aspect_ratio);
+        big.apply_pan(aspect_ratio);
+        assert_eq!(small.center, big.center);
+        assert_ne!(small.center, MandelbrotState::default().center);
+        assert_eq!(small.pan_delta, [0.0; 2]);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    #[cfg_attr(
+        not(feature = "gpu-tests"),
+        ignore = "needs a GPU, see --features gpu-tests"
+    )]
+    fn undo_brings_back_the_palette_of_the_canvas() {
+        let (device, queue) = test_gpu::device();
+        let canvas = test_gpu::canvas(&device, [1, 1]);
+        // As State makes it, but readable
+        let palette_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+            contents: bytemuck::cast_slice(&[PALETTES[0].to_uniform()]),
+        });
+        let view = |palette| RenderedView {
+            center: [-0.5, 0.0],
+            zoom: 1.0,
+            stretch: 1.0,
+            max_iterations: 100,
+            debug_heatmap: false,
+            smooth_coloring: false,
+            palette,
+            size: [1, 1],
+        };
+        let mut mandelbrot_state = MandelbrotState::default();
+        let mut history = CanvasHistory::default();
+
+        // A stroke in palette 0, then a switch to palette 1
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        history.record(&device, &mut encoder, &canvas, Some(view(0)));
+        mandelbrot_state.palette = 1;
+        write_palette(&queue, &palette_buffer, 1);
+
+        // What step_history does with an undo
+        let restored = history.undo(&device, &mut encoder, &canvas, Some(view(1)));
+        queue.submit([encoder.finish()]);
+        let restored_view = restored.unwrap().view.unwrap();
+        mandelbrot_state.show_view(&restored_view);
+        write_palette(&queue, &palette_buffer, restored_view.palette);
+
+        assert_eq!(mandelbrot_state.palette, 0);
+        assert_eq!(
+            test_gpu::read_buffer(&device, &queue, &palette_buffer),
+            bytemuck::bytes_of(&PALETTES[0].to_uniform())
+        );
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    #[cfg_attr(
+        not(feature = "gpu-tests"),
+        ignore = "needs a GPU, see --features gpu-tests"
+    )]
+    fn display_shows_canvas_colors_unchanged_on_any_surface() {
+        let (device, queue) = test_gpu::device();
+        let layout = create_texture_bind_group_layout(&device);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
+        });
+
+        // A mid-gray canvas, as the compute shader would write it
+        let gray = [128, 128, 128, 255];
+        let canvas = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        test_gpu::paint(&queue, &canvas, [0, 0], gray);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(
+                        &canvas.create_view(&wgpu::TextureViewDescriptor::default()),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(
+                        &device.create_sampler(&wgpu::SamplerDescriptor::default()),
+                    ),
+                },
+            ],
+        });
+
+        for format in [
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            wgpu::TextureFormat::Rgba8Unorm,
+        ] {
+            let pipeline =
+                create_render_pipeline(&device, &pipeline_layout, &shader_module, format, None);
+            // Stands in for the surface texture
+            let surface = device.create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: wgpu::Extent3d {
+                    width: 4,
+                    height: 4,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            });
+            let mut encoder =
+                device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+            {
+                let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                    label: None,
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &surface.create_view(&wgpu::TextureViewDescriptor::default()),
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(Color::BLACK),
+                            store: wgpu::StoreOp::Store,
+                        },
+                        depth_slice: None,
+                    })],
+                    depth_stencil_attachment: None,
+                    occlusion_query_set: None,
+                    timestamp_writes: None,
+                });
+                render_pass.set_pipeline(&pipeline);
+                render_pass.set_bind_group(0, &bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            }
+
+            // The stored bytes are what the monitor is sent
+            let pixels = test_gpu::read(&device, &queue, encoder, &surface);
+            for pixel in pixels.chunks(4) {
+                for (shown, written) in pixel.iter().zip(gray) {
+                    assert!(
+                        shown.abs_diff(written) <= 1,
+                        "{:?} shows {:?} for {:?}",
+                        format,
+                        pixel,
+                        gray
+                    );
+                }
+            }
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    #[cfg_attr(
+        not(feature = "gpu-tests"),
+        ignore = "needs a GPU, see --features gpu-tests"
+    )]
+    fn resizing_keeps_painted_pixels() {
+        let (device, queue) = test_gpu::device();
+        let old = test_gpu::canvas(&device, [8, 6]);
+        let marker = [255, 0, 0, 255];
+        test_gpu::paint(&queue, &old, [2, 3], marker);
+        // Off the right edge once the canvas is narrower
+        test_gpu::paint(&queue, &old, [7, 1], [0, 255, 0, 255]);
+
+        // Narrower and taller, so it both crops and grows; wider; then no larger
+        for (size, grows) in [
+            ([5, 10], true),
+            ([10, 6], true),
+            ([5, 4], false),
+            ([8, 6], false),
+        ] {
+            let desc = wgpu::TextureDescriptor {
+                label: None,
+                size: wgpu::Extent3d {
+                    width: size[0],
+                    height: size[1],
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            };
+            let mut encoder =
+                device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+            let ([current, _], grew) = resize_canvas(&device, &mut encoder, &old, &desc);
+            // A grow has to recompute, or the new area stays blank
+            assert_eq!(grew, grows, "resizing to {:?}", size);
+
+            let pixels = test_gpu::read(&device, &queue, encoder, &current);
+            for (i, pixel) in pixels.chunks(4).enumerate() {
+                let at = [i as u32 % size[0], i as u32 / size[0]];
+                let expected = match at {
+                    [2, 3] => marker,
+                    [7, 1] => [0, 255, 0, 255],
+                    _ => [0; 4],
+                };
+                assert_eq!(pixel, expected, "pixel {:?} at size {:?}", at, size);
+            }
+        }
+    }
+
+    // The inverse of screen_to_complex, worked out independently of it
+    fn complex_to_screen(state: &MandelbrotState, point: [f32; 2], aspect_ratio: f32) -> [f64; 2] {
+        let scale = [
+            2.0 * aspect_ratio / state.zoom,
+            2.0 / (state.zoom * state.stretch),
+        ];
+        [
+            ((point[0] - state.center[0]) / scale[0]) as f64 + 0.5,
+            ((point[1] - state.center[1]) / scale[1]) as f64 + 0.5,
+        ]
+    }
+
+    fn assert_near(a: [f64; 2], b: [f64; 2]) {
+        assert!(
+            (a[0] - b[0]).abs() < 1e-5 && (a[1] - b[1]).abs() < 1e-5,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    const STRETCHES: [f32; 5] = [0.125, 0.5, 1.0, 2.0, 8.0];
+    const SCREEN_POINTS: [[f64; 2]; 4] = [[0.0, 0.0], [1.0, 1.0], [0.25, 0.8], [0.5, 0.5]];
+
+    #[test]
+    fn screen_to_complex_round_trips_at_any_stretch() {
+        for stretch in STRETCHES {
+            for zoom in [1.0, 10.0] {
+                for aspect_ratio in [0.5, 1.0, 16.0 / 9.0] {
+                    let state = MandelbrotState {
+                        center: [-0.75, 0.1],
+                        zoom,
+                        stretch,
+                        ..MandelbrotState::default()
+                    };
+                    for screen in SCREEN_POINTS {
+                        let point = state.screen_to_complex(screen, aspect_ratio);
+                        assert_near(complex_to_screen(&state, point, aspect_ratio), screen);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unusable_zooms_and_stretches_are_ignored() {
+        let unusable = [0.0, -0.0, -2.0, f32::NAN, f32::INFINITY, f32::NEG_INFINITY];
+        for value in unusable {
+            let mut state = MandelbrotState {
+                zoom: 4.0,
+                stretch: 2.0,
+                ..MandelbrotState::default()
+            };
+            assert!(!state.set_zoom(value), "zoom {}", value);
+            assert!(!state.zoom_by(value), "zoom factor {}", value);
+            assert!(!state.stretch_by(value), "stretch factor {}", value);
+            state.zoom_at(value, [0.25, 0.75], 1.5);
+            assert_eq!((state.zoom, state.stretch), (4.0, 2.0), "{}", value);
+            assert_eq!(state.center, MandelbrotState::default().center);
+        }
+
+        // Finite factors that overflow are no better
+        let mut state = MandelbrotState {
+            zoom: 2.0,
+            stretch: 2.0,
+            ..MandelbrotState::default()
+        };
+        assert!(!state.zoom_by(f32::MAX));
+        assert!(!state.stretch_by(f32::MAX));
+        assert_eq!((state.zoom, state.stretch), (2.0, 2.0));
+
+        assert!(state.set_zoom(8.0));
+        assert!(state.zoom_by(0.5));
+        assert!(state.stretch_by(3.0));
+        assert_eq!((state.zoom, state.stretch), (4.0, 6.0));
+    }
+
+    #[test]
+    fn stretch_only_scales_the_vertical_axis() {
+        let plain = MandelbrotState::default();
+        for stretch in STRETCHES {
+            let stretched = MandelbrotState {
+                stretch,
+                ..MandelbrotState::default()
+            };
+            let a = plain.screen_delta_to_complex([0.3, 0.4], 1.5);
+            let b = stretched.screen_delta_to_complex([0.3, 0.4], 1.5);
+            assert_eq!(a[0], b[0]);
+            assert!((a[1] - b[1] * stretch).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn zoom_and_drag_keep_their_anchor_at_any_stretch() {
+        let aspect_ratio = 1.5;
+        for stretch in STRETCHES {
+            for screen in SCREEN_POINTS {
+                let mut state = MandelbrotState {
+                    stretch,
+                    ..MandelbrotState::default()
+                };
+                let anchor = state.screen_to_complex(screen, aspect_ratio);
+                state.zoom_at(3.0, screen, aspect_ratio);
+                assert_near(complex_to_screen(&state, anchor, aspect_ratio), screen);
+
+                // The grabbed point follows the cursor
+                let delta = [0.1, -0.2];
+                state.pan_delta = delta;
+                state.apply_pan(aspect_ratio);
+                assert_near(
+                    complex_to_screen(&state, anchor, aspect_ratio),
+                    [screen[0] + delta[0], screen[1] + delta[1]],
+                );
+            }
+        }
     }
 }
@@ -0,0 +1,240 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+// How many failed submissions to keep for reports
+const MAX_FAILURES: usize = 16;
+
+/// What was in flight when a submission raised a GPU error.
+#[derive(Debug)]
+pub struct SubmitFailure {
+    /// Presents so far, as a frame number.
+    pub frame: u64,
+    /// Which feature submitted.
+    pub label: &'static str,
+    /// The passes and copies the submission encoded, in order.
+    pub passes: Vec<&'static str>,
+    /// Hashes of the uniform contents the submission used.
+    pub uniform_hashes: Vec<(&'static str, u64)>,
+    /// The error the scope caught.
+    pub error: String,
+}
+
+/// Recent failed submissions, shared so error scopes that resolve later
+/// (always the case on the web) can still record into it.
+#[derive(Clone, Default)]
+pub struct SubmitLog {
+    failures: Arc<Mutex<VecDeque<SubmitFailure>>>,
+}
+
+impl SubmitLog {
+    pub fn record(&self, failure: SubmitFailure) {
+        log::error!(
+            "GPU error in {} submission (frame {}, passes {:?}): {}",
+            failure.label,
+            failure.frame,
+            failure.passes,
+            failure.error
+        );
+        let Ok(mut failures) = self.failures.lock() else {
+            return;
+        };
+        if failures.len() == MAX_FAILURES {
+            failures.pop_front();
+        }
+        failures.push_back(failure);
+    }
+
+    /// Closes the scopes [`open_scopes`] opened, once the submission they
+    /// cover is in, and records what they caught along with what the
+    /// submission contained.
+    pub fn close_scopes(
+        &self,
+        device: &wgpu::Device,
+        frame: u64,
+        label: &'static str,
+        passes: Vec<&'static str>,
+        uniform_hashes: Vec<(&'static str, u64)>,
+    ) {
+        let validation = device.pop_error_scope();
+        let out_of_memory = device.pop_error_scope();
+        let submit_log = self.clone();
+        let check = async move {
+            let error = match validation.await {
+                Some(error) => Some(error),
+                None => out_of_memory.await,
+            };
+            if let Some(error) = error {
+                submit_log.record(SubmitFailure {
+                    frame,
+                    label,
+                    passes,
+                    uniform_hashes,
+                    error: error.to_string(),
+                });
+            }
+        };
+
+        // Native scopes resolve immediately; the web reports back later
+        #[cfg(not(target_arch = "wasm32"))]
+        pollster::block_on(check);
+        #[cfg(target_arch = "wasm32")]
+        wasm_bindgen_futures::spawn_local(check);
+    }
+
+    /// Appends the recorded failures to a diagnostics report.
+    pub fn write_report(&self, out: &mut String) {
+        let Ok(failures) = self.failures.lock() else {
+            return;
+        };
+        let _ = writeln!(out, "Failed submissions: {}", failures.len());
+        for failure in failures.iter() {
+            let _ = writeln!(
+                out,
+                "  frame {} {} {:?}",
+                failure.frame, failure.label, failure.passes
+            );
+            for (name, hash) in &failure.uniform_hashes {
+                let _ = writeln!(out, "    {} {:016x}", name, hash);
+            }
+            let _ = writeln!(out, "    {}", failure.error);
+        }
+    }
+}
+
+/// Opens the error scopes [`SubmitLog::close_scopes`] reads, so an error
+/// can be pinned on one submission. Call before encoding it.
+pub fn open_scopes(device: &wgpu::Device) {
+    device.push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+}
+
+/// Hashes a uniform's bytes so reports can tell whether two failures saw
+/// the same values without dumping them.
+pub fn hash_uniform(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failure(frame: u64) -> SubmitFailure {
+        SubmitFailure {
+            frame,
+            label: "fractal",
+            passes: vec!["mandelbrot", "display"],
+            uniform_hashes: vec![("params", 0xabc)],
+            error: "Validation Error".into(),
+        }
+    }
+
+    #[test]
+    fn oldest_failures_are_dropped_past_the_limit() {
+        let log = SubmitLog::default();
+        for frame in 0..MAX_FAILURES as u64 + 2 {
+            log.record(failure(frame));
+        }
+
+        let failures = log.failures.lock().unwrap();
+        assert_eq!(failures.len(), MAX_FAILURES);
+        assert_eq!(failures.front().unwrap().frame, 2);
+        assert_eq!(failures.back().unwrap().frame, MAX_FAILURES as u64 + 1);
+    }
+
+    #[test]
+    fn report_lists_each_failure() {
+        let log = SubmitLog::default();
+        let mut empty = String::new();
+        log.write_report(&mut empty);
+        assert_eq!(empty, "Failed submissions: 0\n");
+
+        log.record(failure(7));
+        let mut report = String::new();
+        log.write_report(&mut report);
+        assert_eq!(
+            report,
+            concat!(
+                "Failed submissions: 1\n",
+                "  frame 7 fractal [\"mandelbrot\", \"display\"]\n",
+                "    params 0000000000000abc\n",
+                "    Validation Error\n",
+            )
+        );
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    #[cfg_attr(
+        not(feature = "gpu-tests"),
+        ignore = "needs a GPU, see --features gpu-tests"
+    )]
+    fn scopes_catch_the_submission_that_failed() {
+        let (device, queue) = crate::test_gpu::device();
+        let log = SubmitLog::default();
+
+        // Wants a uniform buffer at binding 0
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(
+                "@group(0) @binding(0) var<uniform> value: vec4<f32>;
+                 @compute @workgroup_size(1) fn main() { _ = value; }"
+                    .into(),
+            ),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: None,
+            layout: None,
+            module: &module,
+            entry_point: None,
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        // ...but is handed a bind group with nothing in it
+        let empty_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[],
+        });
+        let wrong_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &empty_layout,
+            entries: &[],
+        });
+
+        let dispatch = |bind: bool| {
+            open_scopes(&device);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            if bind {
+                let mut pass = encoder.begin_compute_pass(&Default::default());
+                pass.set_pipeline(&pipeline);
+                pass.set_bind_group(0, &wrong_group, &[]);
+                pass.dispatch_workgroups(1, 1, 1);
+            }
+            queue.submit([encoder.finish()]);
+        };
+
+        // A clean submission records nothing
+        dispatch(false);
+        log.close_scopes(&device, 3, "clean", vec!["nothing"], Vec::new());
+        dispatch(true);
+        log.close_scopes(
+            &device,
+            4,
+            "test",
+            vec!["wrong bind group"],
+            vec![("params", 1)],
+        );
+
+        let failures = log.failures.lock().unwrap();
+        assert_eq!(failures.len(), 1);
+        let failure = &failures[0];
+        assert_eq!(failure.frame, 4);
+        assert_eq!(failure.label, "test");
+        assert_eq!(failure.passes, ["wrong bind group"]);
+        assert_eq!(failure.uniform_hashes, [("params", 1)]);
+        assert!(!failure.error.is_empty());
+    }
+}
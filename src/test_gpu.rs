@@ -0,0 +1,101 @@
+//! Helpers for tests that need a GPU. Those tests are ignored unless the
+//! `gpu-tests` feature is on; with it, they run on whatever adapter is
+//! around, software ones included, and fail if there is none.
+
+use crate::export::Readback;
+
+pub fn adapter() -> wgpu::Adapter {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
+    pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+        .expect("GPU tests need an adapter; run without --features gpu-tests to skip them")
+}
+
+pub fn device() -> (wgpu::Device, wgpu::Queue) {
+    open(&adapter())
+}
+
+pub fn open(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Queue) {
+    pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default()))
+        .expect("unable to open a device on the test adapter")
+}
+
+/// An empty RGBA8 texture that can be written and read back.
+pub fn canvas(device: &wgpu::Device, size: [u32; 2]) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Test Canvas"),
+        size: wgpu::Extent3d {
+            width: size[0],
+            height: size[1],
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    })
+}
+
+/// Sets one pixel of `texture`, ahead of the next submission.
+pub fn paint(queue: &wgpu::Queue, texture: &wgpu::Texture, at: [u32; 2], color: [u8; 4]) {
+    queue.write_texture(
+        wgpu::TexelCopyTextureInfo {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d {
+                x: at[0],
+                y: at[1],
+                z: 0,
+            },
+            aspect: wgpu::TextureAspect::All,
+        },
+        &color,
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(4),
+            rows_per_image: Some(1),
+        },
+        wgpu::Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+/// Submits `encoder` and returns all of `texture` as tightly packed RGBA
+/// rows, as it is once the encoded work is done.
+pub fn read(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    mut encoder: wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+) -> Vec<u8> {
+    let readback = Readback::new(device, texture.width(), texture.height());
+    readback.encode_copy(&mut encoder, texture, [0, 0]);
+    let submission = queue.submit([encoder.finish()]);
+    readback.read(device, submission).unwrap()
+}
+
+/// The contents of `buffer`, which needs `COPY_SRC`, once everything
+/// submitted so far is done.
+pub fn read_buffer(device: &wgpu::Device, queue: &wgpu::Queue, buffer: &wgpu::Buffer) -> Vec<u8> {
+    let staging = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Test Staging Buffer"),
+        size: buffer.size(),
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, buffer.size());
+    let submission = queue.submit([encoder.finish()]);
+    let slice = staging.slice(..);
+    slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+    device
+        .poll(wgpu::PollType::WaitForSubmissionIndex(submission))
+        .unwrap();
+    let data = slice.get_mapped_range().to_vec();
+    staging.unmap();
+    data
+}
@@ -0,0 +1,149 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use web_time::Instant;
+
+// Changes closer together than this are one navigation step, so a drag or
+// a burst of wheel zooms becomes a single history entry
+const COALESCE_WINDOW: Duration = Duration::from_millis(500);
+// Oldest entries are forgotten past this
+const MAX_ENTRIES: usize = 300;
+
+/// The part of the view that back/forward navigation restores.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ViewEntry {
+    pub center: [f32; 2],
+    pub zoom: f32,
+    pub stretch: f32,
+}
+
+/// Browser-style back/forward history of views.
+#[derive(Default)]
+pub struct ViewHistory {
+    back: VecDeque<ViewEntry>,
+    forward: Vec<ViewEntry>,
+    current: Option<ViewEntry>,
+    // When the view last changed by anything other than navigation
+    last_change: Option<Instant>,
+}
+
+impl ViewHistory {
+    /// Call once per frame with the view about to be shown. A change that
+    /// follows a quiet spell pushes the view it left onto the back stack and
+    /// clears the forward stack; changes within the coalescing window of the
+    /// previous one just move the current entry.
+    pub fn observe(&mut self, view: ViewEntry, now: Instant) {
+        let Some(current) = self.current else {
+            self.current = Some(view);
+            return;
+        };
+        if current == view {
+            return;
+        }
+
+        let coalesce = self
+            .last_change
+            .is_some_and(|last| now.duration_since(last) < COALESCE_WINDOW);
+        if !coalesce {
+            if self.back.len() == MAX_ENTRIES {
+                self.back.pop_front();
+            }
+            self.back.push_back(current);
+            self.forward.clear();
+        }
+        self.current = Some(view);
+        self.last_change = Some(now);
+    }
+
+    /// Steps back, returning the view to restore.
+    pub fn back(&mut self) -> Option<ViewEntry> {
+        let target = self.back.pop_back()?;
+        self.forward.extend(self.current);
+        Some(self.navigate(target))
+    }
+
+    /// Steps forward again after going back, returning the view to restore.
+    pub fn forward(&mut self) -> Option<ViewEntry> {
+        let target = self.forward.pop()?;
+        self.back.extend(self.current);
+        Some(self.navigate(target))
+    }
+
+    fn navigate(&mut self, target: ViewEntry) -> ViewEntry {
+        self.current = Some(target);
+        // The next change starts a new entry instead of coalescing into this one
+        self.last_change = None;
+        target
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view(zoom: f32) -> ViewEntry {
+        ViewEntry {
+            center: [-0.5, 0.0],
+            zoom,
+            stretch: 1.0,
+        }
+    }
+
+    #[test]
+    fn changes_within_the_window_are_one_entry() {
+        let start = Instant::now();
+        let mut history = ViewHistory::default();
+        history.observe(view(1.0), start);
+        // A burst of wheel zooms, each well inside the window of the last
+        for step in 1..=20 {
+            history.observe(
+                view(1.0 + step as f32),
+                start + step * Duration::from_millis(100),
+            );
+        }
+        assert_eq!(history.back(), Some(view(1.0)));
+        assert_eq!(history.back(), None);
+    }
+
+    #[test]
+    fn a_change_after_the_window_is_a_new_entry() {
+        let start = Instant::now();
+        let mut history = ViewHistory::default();
+        history.observe(view(1.0), start);
+        history.observe(view(2.0), start);
+        history.observe(view(4.0), start + COALESCE_WINDOW);
+        assert_eq!(history.back(), Some(view(2.0)));
+        assert_eq!(history.back(), Some(view(1.0)));
+        assert_eq!(history.back(), None);
+    }
+
+    #[test]
+    fn back_and_forward_move_through_entries() {
+        let start = Instant::now();
+        let mut history = ViewHistory::default();
+        for step in 0..3 {
+            history.observe(view(step as f32), start + step * COALESCE_WINDOW * 2);
+        }
+        assert_eq!(history.back(), Some(view(1.0)));
+        assert_eq!(history.back(), Some(view(0.0)));
+        assert_eq!(history.forward(), Some(view(1.0)));
+        assert_eq!(history.forward(), Some(view(2.0)));
+        assert_eq!(history.forward(), None);
+        // Seeing the restored view again is not a change
+        history.observe(view(2.0), start + COALESCE_WINDOW * 10);
+        assert_eq!(history.back(), Some(view(1.0)));
+    }
+
+    #[test]
+    fn a_change_after_going_back_drops_the_forward_stack() {
+        let start = Instant::now();
+        let mut history = ViewHistory::default();
+        history.observe(view(1.0), start);
+        history.observe(view(2.0), start + COALESCE_WINDOW);
+        assert_eq!(history.back(), Some(view(1.0)));
+        // Right after navigating, even a quick change starts a new entry
+        history.observe(view(3.0), start + COALESCE_WINDOW);
+        assert_eq!(history.forward(), None);
+        assert_eq!(history.back(), Some(view(1.0)));
+    }
+}